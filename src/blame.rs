@@ -0,0 +1,187 @@
+//! Attribution of counted lines to the authors who last changed them.
+
+use std::{collections::HashMap, io::Cursor, path::Path};
+
+use git2::{BlameOptions, Repository};
+
+use crate::{
+    classify_reader, from_memory,
+    git::{repo_relative, tree_files},
+    language_name, path_order, Event, Options, Skipped, Stats,
+};
+
+/// An author, as mapped by the repository's mailmap.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Author {
+    pub name: String,
+    pub email: String,
+}
+
+/// The lines last changed by a single author.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorCount {
+    pub author: Author,
+    /// Line counts keyed by language name, or extension for unknown languages.
+    pub languages: HashMap<String, Stats>,
+    pub total: Stats,
+}
+
+/// The lines of a revision attributed to their authors.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BlameResult {
+    /// Every author owning lines, sorted by name and email.
+    pub authors: Vec<AuthorCount>,
+    pub total: Stats,
+    /// Every file that wasn't attributed, sorted by path.
+    pub skipped: Vec<Skipped>,
+}
+
+/// Attributes every line under `path` as of the revision `rev` to the author of the commit
+/// that last changed it, honoring the repository's mailmap.
+///
+/// Lines are classified like [`crate::count_rev`] does, so each author owns code, comment,
+/// documentation and blank lines separately.
+pub fn blame(path: &Path, rev: &str, options: &Options) -> Result<BlameResult, git2::Error> {
+    let repo = Repository::discover(path)?;
+    let prefix = repo_relative(&repo, path)?;
+    let commit = repo.revparse_single(rev)?.peel_to_commit()?;
+
+    let mut authors = HashMap::<_, HashMap<String, Stats>>::new();
+    let mut skipped = Vec::new();
+    let files = tree_files(&repo, &commit.tree()?, &prefix, path, options)?;
+    for file in files
+        .into_iter()
+        .filter(|file| options.may_count(&file.path))
+    {
+        let blob = repo.find_blob(file.oid)?;
+        let mut lines = Vec::new();
+        let classified = classify_reader(
+            &file.path,
+            Cursor::new(blob.content()),
+            options,
+            |event| match event {
+                Event::Line(language, kind) => lines.push((language, kind)),
+                Event::Restart => lines.clear(),
+            },
+        );
+        let language = match from_memory(classified) {
+            Ok(classified) => classified.language,
+            Err(reason) => {
+                skipped.push(Skipped {
+                    path: file.display,
+                    reason,
+                });
+                continue;
+            }
+        };
+        let name = language_name(&file.path, language);
+        if !options.counts_language(&name) {
+            continue;
+        }
+
+        let blame = repo.blame_file(
+            &file.path,
+            Some(
+                BlameOptions::new()
+                    .newest_commit(commit.id())
+                    .use_mailmap(true),
+            ),
+        )?;
+        let mut lines = lines.into_iter();
+        for hunk in blame.iter() {
+            let author = hunk
+                .final_signature()
+                .map(|signature| Author {
+                    name: String::from_utf8_lossy(signature.name_bytes()).into_owned(),
+                    email: String::from_utf8_lossy(signature.email_bytes()).into_owned(),
+                })
+                .unwrap_or_default();
+            let languages = authors.entry(author).or_default();
+            for (embedded, kind) in lines.by_ref().take(hunk.lines_in_hunk()) {
+                let name = embedded.map_or(name.as_str(), |language| language.name);
+                match languages.get_mut(name) {
+                    Some(stats) => stats.add_line(kind),
+                    None => languages
+                        .entry(name.to_string())
+                        .or_default()
+                        .add_line(kind),
+                }
+            }
+        }
+    }
+
+    skipped.sort_by(|a, b| path_order(&a.path, &b.path));
+    let mut res = BlameResult {
+        skipped,
+        ..Default::default()
+    };
+    for (author, languages) in authors {
+        let mut total = Stats::default();
+        for stats in languages.values() {
+            total += *stats;
+        }
+        res.total += total;
+        res.authors.push(AuthorCount {
+            author,
+            languages,
+            total,
+        });
+    }
+    res.authors.sort_by(|a, b| a.author.cmp(&b.author));
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestRepo;
+
+    #[test]
+    fn blames_authors_through_the_mailmap() {
+        let repo = TestRepo::new("blame");
+        repo.commit_as(
+            ("Old", "old@example.com"),
+            &[
+                ("a.rs", "fn a() {}\n// a\n"),
+                (".mailmap", "A <a@example.com> <old@example.com>\n"),
+            ],
+            0,
+        );
+        repo.commit_as(
+            ("B", "b@example.com"),
+            &[("a.rs", "fn a() {}\n// a\nfn b() {}\n")],
+            1,
+        );
+
+        let res = blame(repo.path(), "HEAD", &Options::default()).unwrap();
+        let rust: Vec<_> = res
+            .authors
+            .iter()
+            .map(|count| (count.author.name.as_str(), count.languages["Rust"]))
+            .collect();
+        let stats = |code, comments| Stats {
+            code,
+            comments,
+            ..Stats::default()
+        };
+        assert_eq!(rust, [("A", stats(1, 1)), ("B", stats(1, 0))]);
+        assert_eq!(res.total.lines(), 4);
+    }
+
+    #[test]
+    fn lists_skipped_files_by_path() {
+        let repo = TestRepo::new("blame-skipped");
+        repo.commit(&[("a-b.min.js", "a;\n"), ("a/x.min.js", "x;\n")], 0);
+
+        let res = blame(repo.path(), "HEAD", &Options::default()).unwrap();
+        let paths: Vec<_> = res.skipped.iter().map(|skip| skip.path.clone()).collect();
+        // Git orders the tree `a` after `a-b.min.js`.
+        assert_eq!(
+            paths,
+            [
+                repo.path().join("a/x.min.js"),
+                repo.path().join("a-b.min.js")
+            ]
+        );
+    }
+}
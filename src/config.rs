@@ -0,0 +1,255 @@
+//! User defined languages, read from `lc.toml` files like the one below. The file in the
+//! user's config directory is read, then `lc.toml` in the current directory, then any
+//! given with `--config`. Files in the directories being counted are only read when
+//! they're one of those, so counting another project from outside of it doesn't pick up
+//! its `lc.toml`.
+//!
+//! ```toml
+//! [languages.MyDsl]
+//! extensions = ["dsl"]
+//! filenames = ["Dslfile"]
+//! line_comments = ["--"]
+//! block_comments = [["(*", "*)"]]
+//! nested_comments = []
+//! doc_line_comments = ["---"]
+//! doc_block_comments = [["(**", "*)"]]
+//! nested_doc_comments = []
+//! strings = [["\"", "\""]]
+//! verbatim_strings = [["'", "'"]]
+//! doc_strings = [["\"\"\"", "\"\"\""]]
+//! branches = ["if", "while", "&&", "||"]
+//! minifiable = false
+//! ```
+//!
+//! Comments can be nested inside `nested_doc_comments`, like in `nested_comments`.
+//! `strings` use `\` escapes while `verbatim_strings` don't. `doc_strings` are strings
+//! with escapes that count as documentation when they start a line. `branches` are the
+//! keywords and operators counted towards complexity. Files of `minifiable` languages are
+//! skipped as minified when their lines are very long. Delimiters can't be empty. A
+//! language with the same name as a built-in one replaces it.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{CommentSyntax, Language, Languages, StringSyntax};
+
+/// The name of config files.
+pub const CONFIG_FILE: &str = "lc.toml";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    languages: BTreeMap<String, LanguageConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LanguageConfig {
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    filenames: Vec<String>,
+    #[serde(default)]
+    line_comments: Vec<String>,
+    #[serde(default)]
+    block_comments: Vec<(String, String)>,
+    #[serde(default)]
+    nested_comments: Vec<(String, String)>,
+    #[serde(default)]
+    doc_line_comments: Vec<String>,
+    #[serde(default)]
+    doc_block_comments: Vec<(String, String)>,
+    #[serde(default)]
+    nested_doc_comments: Vec<(String, String)>,
+    #[serde(default)]
+    strings: Vec<(String, String)>,
+    #[serde(default)]
+    verbatim_strings: Vec<(String, String)>,
+    #[serde(default)]
+    doc_strings: Vec<(String, String)>,
+    #[serde(default)]
+    branches: Vec<String>,
+    #[serde(default)]
+    minifiable: bool,
+}
+
+fn leak(s: String) -> &'static str {
+    s.leak()
+}
+
+impl LanguageConfig {
+    /// The first key with an empty delimiter, which the scanner couldn't make progress on.
+    fn empty_delimiter(&self) -> Option<&'static str> {
+        let tokens = |tokens: &[String]| tokens.iter().any(String::is_empty);
+        let pairs = |pairs: &[(String, String)]| {
+            pairs
+                .iter()
+                .any(|(start, end)| start.is_empty() || end.is_empty())
+        };
+        [
+            ("line_comments", tokens(&self.line_comments)),
+            ("block_comments", pairs(&self.block_comments)),
+            ("nested_comments", pairs(&self.nested_comments)),
+            ("doc_line_comments", tokens(&self.doc_line_comments)),
+            ("doc_block_comments", pairs(&self.doc_block_comments)),
+            ("nested_doc_comments", pairs(&self.nested_doc_comments)),
+            ("strings", pairs(&self.strings)),
+            ("verbatim_strings", pairs(&self.verbatim_strings)),
+            ("doc_strings", pairs(&self.doc_strings)),
+            ("branches", tokens(&self.branches)),
+        ]
+        .into_iter()
+        .find_map(|(key, empty)| empty.then_some(key))
+    }
+
+    /// Builds the language described by this config. Languages live for the rest of the
+    /// program, like the built-in ones.
+    fn into_language(self, name: String) -> &'static Language<'static> {
+        let comments = self
+            .line_comments
+            .into_iter()
+            .map(|start| CommentSyntax::LineStart(leak(start)))
+            .chain(
+                self.block_comments
+                    .into_iter()
+                    .map(|(start, end)| CommentSyntax::Range(leak(start), leak(end))),
+            )
+            .chain(
+                self.nested_comments
+                    .into_iter()
+                    .map(|(start, end)| CommentSyntax::Nested(leak(start), leak(end))),
+            )
+            .chain(
+                self.doc_line_comments
+                    .into_iter()
+                    .map(|start| CommentSyntax::DocLineStart(leak(start))),
+            )
+            .chain(
+                self.doc_block_comments
+                    .into_iter()
+                    .map(|(start, end)| CommentSyntax::DocRange(leak(start), leak(end))),
+            )
+            .chain(
+                self.nested_doc_comments
+                    .into_iter()
+                    .map(|(start, end)| CommentSyntax::NestedDoc(leak(start), leak(end))),
+            )
+            .collect::<Vec<_>>();
+        let strings = self
+            .strings
+            .into_iter()
+            .map(|(start, end)| StringSyntax::Escaped(leak(start), leak(end)))
+            .chain(
+                self.verbatim_strings
+                    .into_iter()
+                    .map(|(start, end)| StringSyntax::Verbatim(leak(start), leak(end))),
+            )
+            .chain(
+                self.doc_strings
+                    .into_iter()
+                    .map(|(start, end)| StringSyntax::Doc(leak(start), leak(end))),
+            )
+            .collect::<Vec<_>>();
+
+        let branches = self.branches.into_iter().map(leak).collect::<Vec<_>>();
+
+        Box::leak(Box::new(Language {
+            minifiable: self.minifiable,
+            ..Language::new(leak(name), comments.leak())
+                .with_strings(strings.leak())
+                .with_branches(branches.leak())
+        }))
+    }
+}
+
+impl Languages {
+    /// Adds the languages defined in the config file at `path`.
+    pub fn load(&mut self, path: &Path) -> io::Result<()> {
+        let named =
+            |err: io::Error| io::Error::new(err.kind(), format!("{}: {err}", path.display()));
+        let invalid = |message: String| named(io::Error::new(io::ErrorKind::InvalidData, message));
+        let src = fs::read_to_string(path).map_err(named)?;
+        let config: Config = toml::from_str(&src).map_err(|err| invalid(err.to_string()))?;
+        for (name, language) in config.languages {
+            if let Some(key) = language.empty_delimiter() {
+                return Err(invalid(format!("empty delimiter in {key} of {name}")));
+            }
+            let extensions = language.extensions.clone();
+            let filenames = language.filenames.clone();
+            self.add(language.into_language(name), extensions, filenames);
+        }
+        Ok(())
+    }
+
+    /// The built-in languages along with those from the config file in the user's config
+    /// directory and `lc.toml` in the current directory, in that order of precedence
+    /// from lowest to highest. Missing config files are skipped. Config files in the
+    /// directories being counted aren't looked for.
+    pub fn from_config() -> io::Result<Self> {
+        let mut languages = Self::default();
+        let paths = dirs::config_dir()
+            .map(|dir| dir.join("lc").join(CONFIG_FILE))
+            .into_iter()
+            .chain([PathBuf::from(CONFIG_FILE)]);
+        for path in paths.filter(|path| path.is_file()) {
+            languages.load(&path)?;
+        }
+        Ok(languages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    /// Loads `src` as a config file, in a directory named after `name`.
+    fn load(name: &str, src: &str) -> io::Result<Languages> {
+        let dir = TempDir::new(name);
+        dir.write(&[(CONFIG_FILE, src)]);
+        let mut languages = Languages::default();
+        languages
+            .load(&dir.path.join(CONFIG_FILE))
+            .map(|()| languages)
+    }
+
+    #[test]
+    fn loads_languages() {
+        let src = "[languages.Foo]\nextensions = [\"foo\"]\nline_comments = [\"%\"]\n";
+        let languages = load("loads", src).unwrap();
+        assert_eq!(languages.from_extension("foo").unwrap().name, "Foo");
+    }
+
+    #[test]
+    fn rejects_empty_delimiters() {
+        for (key, value) in [
+            ("line_comments", "[\"\"]"),
+            ("block_comments", "[[\"/*\", \"\"]]"),
+            ("nested_doc_comments", "[[\"\", \"*/\"]]"),
+            ("strings", "[[\"\", \"\"]]"),
+            ("branches", "[\"if\", \"\"]"),
+        ] {
+            let src = format!("[languages.Foo]\n{key} = {value}\n");
+            let err = load("empty", &src).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().contains("lc-empty-"), "{err}");
+            assert!(err
+                .to_string()
+                .ends_with(&format!("empty delimiter in {key} of Foo")));
+        }
+    }
+
+    #[test]
+    fn names_files_that_cant_be_read() {
+        let path = Path::new("no/such/lc.toml");
+        let err = Languages::default().load(path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().starts_with("no/such/lc.toml: "), "{err}");
+    }
+}
@@ -0,0 +1,137 @@
+//! Differences between two counts, like those of two revisions of a codebase.
+
+use std::{collections::HashMap, ops::AddAssign, path::PathBuf};
+
+use crate::{CountResult, Stats};
+
+/// How the line counts of a file or language changed between two counts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Delta {
+    /// Lines gained in each category.
+    pub added: Stats,
+    /// Lines lost in each category.
+    pub removed: Stats,
+}
+
+impl Delta {
+    /// The change from `old` to `new`, each category either gaining or losing lines.
+    pub fn between(old: Stats, new: Stats) -> Self {
+        let gained = |old: usize, new: usize| new.saturating_sub(old);
+        Self {
+            added: Stats {
+                code: gained(old.code, new.code),
+                comments: gained(old.comments, new.comments),
+                docs: gained(old.docs, new.docs),
+                blanks: gained(old.blanks, new.blanks),
+                complexity: gained(old.complexity, new.complexity),
+            },
+            removed: Stats {
+                code: gained(new.code, old.code),
+                comments: gained(new.comments, old.comments),
+                docs: gained(new.docs, old.docs),
+                blanks: gained(new.blanks, old.blanks),
+                complexity: gained(new.complexity, old.complexity),
+            },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl AddAssign for Delta {
+    fn add_assign(&mut self, rhs: Self) {
+        self.added += rhs.added;
+        self.removed += rhs.removed;
+    }
+}
+
+/// How the line counts of a single file changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDelta {
+    pub path: PathBuf,
+    pub language: String,
+    pub delta: Delta,
+}
+
+/// The changes between two counts.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DiffResult {
+    /// Changes keyed by language, summed over the changes of each file.
+    pub languages: HashMap<String, Delta>,
+    /// Every file that changed, sorted by path.
+    pub files: Vec<FileDelta>,
+    pub total: Delta,
+}
+
+/// Compares two counts file by file. Files are matched by path and language, so a file
+/// that changed language counts as removed from one language and added to the other. Code
+/// embedded in other languages is compared separately, see
+/// [`crate::FileCount::languages`].
+///
+/// Paths are compared as they are, see [`CountResult::strip_prefix`] to compare counts of
+/// different directories.
+pub fn diff(old: &CountResult, new: &CountResult) -> DiffResult {
+    let mut files = HashMap::<_, (Stats, Stats)>::new();
+    for file in &old.files {
+        for (language, stats) in file.languages() {
+            files
+                .entry((file.path.clone(), language.to_string()))
+                .or_default()
+                .0 += stats;
+        }
+    }
+    for file in &new.files {
+        for (language, stats) in file.languages() {
+            files
+                .entry((file.path.clone(), language.to_string()))
+                .or_default()
+                .1 += stats;
+        }
+    }
+
+    let mut files: Vec<_> = files
+        .into_iter()
+        .map(|((path, language), (old, new))| FileDelta {
+            path,
+            language,
+            delta: Delta::between(old, new),
+        })
+        .filter(|file| !file.delta.is_empty())
+        .collect();
+    files.sort_by(|a, b| (&a.path, &a.language).cmp(&(&b.path, &b.language)));
+
+    let mut res = DiffResult::default();
+    for file in &files {
+        *res.languages.entry(file.language.clone()).or_default() += file.delta;
+        res.total += file.delta;
+    }
+    res.files = files;
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{test_util::TestRepo, Options};
+
+    #[test]
+    fn compares_revisions() {
+        let repo = TestRepo::new("diff");
+        repo.commit(&[("a.rs", "fn a() {}\n"), ("b.py", "# b\nb = 1\n")], 0);
+        repo.commit(&[("a.rs", "// a\nfn a() {}\nfn b() {}\n")], 1);
+
+        let old = repo.count("HEAD~1", &Options::default());
+        let new = repo.count("HEAD", &Options::default());
+        let res = diff(&old, &new);
+        assert_eq!(res.files.len(), 1);
+        assert_eq!(res.files[0].path, Path::new("a.rs"));
+        let rust = res.languages["Rust"];
+        assert_eq!((rust.added.code, rust.added.comments), (1, 1));
+        assert_eq!(rust.removed, Stats::default());
+        assert_eq!(res.total, rust);
+    }
+}
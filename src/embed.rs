@@ -0,0 +1,360 @@
+//! Classification of code embedded in other languages, like scripts in HTML pages and code
+//! blocks in Markdown.
+
+use crate::{
+    scanner::{LineKind, Scanner},
+    EmbedSyntax, Language, Languages,
+};
+
+/// Where embedded code ends.
+enum End {
+    /// At the closing tag of the element with this name.
+    Element(&'static str),
+    /// At a fence of at least `len` times `c`.
+    Fence { c: char, len: usize },
+}
+
+impl End {
+    fn matches(&self, line: &str) -> bool {
+        match *self {
+            Self::Element(tag) => find_ignore_case(line, "</", tag).is_some(),
+            Self::Fence { c, len } => {
+                let line = line.trim();
+                line.len() >= len && line.chars().all(|x| x == c)
+            }
+        }
+    }
+}
+
+/// Embedded code and the scanner of its language, if that's known.
+struct Region {
+    end: End,
+    scanner: Option<(&'static Language<'static>, Scanner<'static>)>,
+}
+
+/// An element whose opening tag continues on the next line.
+struct Opening {
+    tag: &'static str,
+    default: &'static str,
+    /// The language named by the attributes so far.
+    named: Option<Option<&'static Language<'static>>>,
+}
+
+/// The position of `</tag` or `<tag`, depending on `open`, in `line`, ignoring case.
+fn find_ignore_case(line: &str, open: &str, tag: &str) -> Option<usize> {
+    let needle = [open.as_bytes(), tag.as_bytes()].concat();
+    line.as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(&needle))
+}
+
+/// The value of the attribute called `name` in the attributes of a tag.
+fn attribute<'s>(attributes: &'s str, name: &str) -> Option<&'s str> {
+    let mut rest = attributes;
+    while let Some(i) = find_ignore_case(rest, "", name) {
+        let before = rest[..i].chars().next_back();
+        let after = rest[i + name.len()..].trim_start();
+        rest = &rest[i + name.len()..];
+        let Some(value) = after.strip_prefix('=') else {
+            continue;
+        };
+        if !before.is_none_or(char::is_whitespace) {
+            continue;
+        }
+        let value = value.trim_start();
+        return match value.chars().next()? {
+            quote @ ('"' | '\'') => value[1..].split(quote).next(),
+            _ => value.split(|c: char| c.is_whitespace() || c == '>').next(),
+        };
+    }
+    None
+}
+
+/// The language named by the `lang` or `type` attribute of an element. `None` if the
+/// attributes don't name one, `Some(None)` if they name one that isn't known.
+fn attribute_language(
+    attributes: &str,
+    languages: &Languages,
+) -> Option<Option<&'static Language<'static>>> {
+    let name = attribute(attributes, "lang").or_else(|| {
+        // Types like `text/javascript` or `text/x-typescript`, modules are scripts.
+        let kind = attribute(attributes, "type")?.rsplit('/').next()?;
+        Some(kind.strip_prefix("x-").unwrap_or(kind)).filter(|kind| *kind != "module")
+    })?;
+    Some(languages.named(name))
+}
+
+/// Where the parts of the line being classified go.
+#[derive(Debug, Clone, Copy)]
+enum Route {
+    /// To the file's own language.
+    Host,
+    /// To the file's own language, on the line closing embedded code.
+    Closing,
+    /// To the language of the embedded code.
+    Region,
+}
+
+/// Classifies the lines of a file, switching to the scanner of another language for the
+/// code embedded in it.
+pub(crate) struct Classifier<'a> {
+    language: Option<&'static Language<'static>>,
+    languages: &'a Languages,
+    scanner: Option<Scanner<'static>>,
+    opening: Option<Opening>,
+    region: Option<Region>,
+    /// Where the current line goes, once its first part was scanned.
+    route: Option<Route>,
+    /// Whether the current line is blank so far, for languages that aren't known.
+    blank: bool,
+    /// The branches found in embedded code that ended.
+    branches: Vec<(&'static Language<'static>, usize)>,
+}
+
+impl<'a> Classifier<'a> {
+    pub(crate) fn new(
+        language: Option<&'static Language<'static>>,
+        languages: &'a Languages,
+    ) -> Self {
+        Self {
+            language,
+            languages,
+            scanner: language.map(Scanner::new),
+            opening: None,
+            region: None,
+            route: None,
+            blank: true,
+            branches: Vec::new(),
+        }
+    }
+
+    /// Scans `part` of the current line, see [`Classifier::end_line`]. Only the first part
+    /// of a line starts or ends embedded code.
+    pub(crate) fn scan(&mut self, part: &str) {
+        self.blank &= part.trim().is_empty();
+        let first = self.route.is_none();
+        let route = *self.route.get_or_insert_with(|| match &self.region {
+            Some(region) if !region.end.matches(part) => Route::Region,
+            Some(_) => Route::Closing,
+            None => Route::Host,
+        });
+        if first && matches!(route, Route::Closing) {
+            // The line closing embedded code belongs to the file's own language.
+            self.end_region();
+        }
+        if let Route::Region = route {
+            if let Some(Region {
+                scanner: Some((_, scanner)),
+                ..
+            }) = &mut self.region
+            {
+                scanner.scan(part);
+            }
+            return;
+        }
+
+        let in_code = self.scanner.as_ref().is_none_or(Scanner::in_code);
+        if let Some(scanner) = &mut self.scanner {
+            scanner.scan(part);
+        }
+        if !first || matches!(route, Route::Closing) {
+            return;
+        }
+        if let Some(opening) = self.opening.take() {
+            self.continue_element(opening, part);
+        } else if in_code {
+            self.start_region(part);
+        }
+    }
+
+    /// Ends the current line, returning its kind along with the language of the code
+    /// embedded in it, if it's embedded code.
+    pub(crate) fn end_line(&mut self) -> (Option<&'static Language<'static>>, LineKind) {
+        let blank = std::mem::replace(&mut self.blank, true);
+        let end = |scanner: Option<&mut Scanner>| match scanner {
+            Some(scanner) => scanner.end_line(),
+            None if blank => LineKind::Blank,
+            None => LineKind::Code,
+        };
+        match (self.route.take(), &mut self.region) {
+            (
+                Some(Route::Region),
+                Some(Region {
+                    scanner: Some((language, scanner)),
+                    ..
+                }),
+            ) => (Some(*language), scanner.end_line()),
+            (Some(Route::Region), _) => (None, end(None)),
+            _ => (None, end(self.scanner.as_mut())),
+        }
+    }
+
+    fn end_region(&mut self) {
+        if let Some(Region {
+            scanner: Some((language, scanner)),
+            ..
+        }) = self.region.take()
+        {
+            self.branches.push((language, scanner.branches));
+        }
+    }
+
+    /// Starts embedded code after `line` if it opens an element or fence embedding code.
+    fn start_region(&mut self, line: &str) {
+        let Some(language) = self.language else {
+            return;
+        };
+        let trimmed = line.trim_start();
+        for sntx in language.embedded {
+            match *sntx {
+                EmbedSyntax::Element(tag, default) => {
+                    let Some(rest) = trimmed
+                        .get(..tag.len() + 1)
+                        .filter(|start| find_ignore_case(start, "<", tag) == Some(0))
+                        .map(|start| &trimmed[start.len()..])
+                    else {
+                        continue;
+                    };
+                    if rest.starts_with(|c: char| !c.is_whitespace() && c != '>') {
+                        continue;
+                    }
+                    let opening = Opening {
+                        tag,
+                        default,
+                        named: None,
+                    };
+                    return self.continue_element(opening, rest);
+                }
+                EmbedSyntax::Fence => {
+                    let Some(c) = trimmed.chars().next().filter(|c| matches!(c, '`' | '~')) else {
+                        continue;
+                    };
+                    let len = trimmed.len() - trimmed.trim_start_matches(c).len();
+                    if len < 3 {
+                        continue;
+                    }
+                    let info = trimmed[len..]
+                        .split(|c: char| c.is_whitespace() || matches!(c, ',' | '{' | '}'))
+                        .find(|word| !word.is_empty());
+                    let language = info.and_then(|info| self.languages.named(info));
+                    self.region = Some(Region {
+                        end: End::Fence { c, len },
+                        scanner: language.map(|language| (language, Scanner::new(language))),
+                    });
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Continues the opening tag of an element with the attributes in `rest`, starting
+    /// embedded code after the tag unless the element is closed on the same line.
+    fn continue_element(&mut self, mut opening: Opening, rest: &str) {
+        let end = rest.find('>');
+        let attributes = &rest[..end.unwrap_or(rest.len())];
+        opening.named = opening
+            .named
+            .or_else(|| attribute_language(attributes, self.languages));
+        let Some(end) = end else {
+            self.opening = Some(opening);
+            return;
+        };
+        let closed = attributes.ends_with('/')
+            || find_ignore_case(&rest[end..], "</", opening.tag).is_some();
+        if closed {
+            return;
+        }
+        let language = opening
+            .named
+            .unwrap_or_else(|| self.languages.from_extension(opening.default));
+        self.region = Some(Region {
+            end: End::Element(opening.tag),
+            scanner: language.map(|language| (language, Scanner::new(language))),
+        });
+    }
+
+    /// The branches found in code of the file's own language, and those found in each
+    /// language embedded in it.
+    pub(crate) fn finish(mut self) -> (usize, Vec<(&'static Language<'static>, usize)>) {
+        self.end_region();
+        let branches = self.scanner.map_or(0, |scanner| scanner.branches);
+        (branches, self.branches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scanner::LineKind::*, LANGUAGES};
+
+    fn lines(ext: &str, src: &str) -> Vec<(Option<&'static str>, LineKind)> {
+        let languages = Languages::default();
+        let mut classifier = Classifier::new(Some(&LANGUAGES[ext]), &languages);
+        src.lines()
+            .map(|line| {
+                classifier.scan(line);
+                let (language, kind) = classifier.end_line();
+                (language.map(|language| language.name), kind)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scripts_and_styles_in_html() {
+        let src = "<!-- page -->\n<script>\n// init\nlet x = 1;\n</script>\n<STYLE type=\"text/css\">\n/* body */\n</style>\n<script src=\"a.js\"></script>";
+        assert_eq!(
+            lines("html", src),
+            [
+                (None, Comment),
+                (None, Code),
+                (Some("javascript"), Comment),
+                (Some("javascript"), Code),
+                (None, Code),
+                (None, Code),
+                (Some("css"), Comment),
+                (None, Code),
+                (None, Code),
+            ]
+        );
+    }
+
+    #[test]
+    fn attributes_name_the_embedded_language() {
+        let src = "<script\n  lang=\"ts\">\n// typed\n</script>\n<style lang='unknown'>\n\n.a {}\n</style>";
+        assert_eq!(
+            lines("vue", src),
+            [
+                (None, Code),
+                (None, Code),
+                (Some("TypeScript"), Comment),
+                (None, Code),
+                (None, Code),
+                (None, Blank),
+                (None, Code),
+                (None, Code),
+            ]
+        );
+    }
+
+    #[test]
+    fn fenced_code_in_markdown() {
+        let src =
+            "# Title\n```rust\n// comment\n```\n~~~~ py\n# comment\n```\n~~~~\n```\ntext\n```";
+        assert_eq!(
+            lines("md", src),
+            [
+                (None, Code),
+                (None, Code),
+                (Some("Rust"), Comment),
+                (None, Code),
+                (None, Code),
+                (Some("Python"), Comment),
+                (Some("Python"), Code),
+                (None, Code),
+                (None, Code),
+                (None, Code),
+                (None, Code),
+            ]
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! Decoding of files that aren't UTF-8, like Latin-1 sources and UTF-16 files from Windows,
+//! a line at a time.
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use encoding_rs::{Decoder, DecoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+use crate::{skip, SkipReason};
+
+/// How many bytes are looked at to tell UTF-16 without a BOM apart.
+const SNIFF_LEN: usize = 8000;
+/// How many bytes of a line are kept in memory at most, longer lines are read in parts.
+const MAX_LINE: usize = 64 * 1024;
+
+/// The UTF-16 variant `bytes` are in if they look like UTF-16 without a BOM. Mostly ASCII
+/// text has a NUL in every other byte in UTF-16, and few in the bytes in between, which
+/// are only NUL for characters like U+0100.
+fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    let pairs = sample.len() / 2;
+    let (mut even, mut odd) = (0, 0);
+    for pair in sample.chunks_exact(2) {
+        even += usize::from(pair[0] == 0);
+        odd += usize::from(pair[1] == 0);
+    }
+    let few = |nuls: usize| nuls * 10 < pairs;
+    if few(even) && odd * 2 > pairs {
+        Some(UTF_16LE)
+    } else if few(odd) && even * 2 > pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// The encoding of a file starting with `head`: `encoding` if given, else the one named by
+/// its BOM or UTF-16 if it looks like it. `None` stands for UTF-8 if the file is valid UTF-8
+/// and Windows-1252, a superset of Latin-1, for anything else. Files with NULs that aren't
+/// UTF-16 are binary.
+fn detect(head: &[u8], encoding: Option<&'static Encoding>) -> Result<Detected, SkipReason> {
+    let named = encoding.or_else(|| Encoding::for_bom(head).map(|(encoding, _)| encoding));
+    let sniffed = named.is_none().then(|| sniff_utf16(head)).flatten();
+    let encoding = named.or(sniffed);
+    let utf16 = encoding.is_some_and(|encoding| encoding == UTF_16LE || encoding == UTF_16BE);
+    if !utf16 && skip::is_binary(head) {
+        return Err(SkipReason::Binary);
+    }
+    Ok(Detected {
+        encoding,
+        sniffed: sniffed.is_some(),
+    })
+}
+
+struct Detected {
+    encoding: Option<&'static Encoding>,
+    /// Whether the encoding was only guessed to be UTF-16 from the file's NULs.
+    sniffed: bool,
+}
+
+impl Detected {
+    /// Why a file that isn't valid text in the detected encoding is skipped.
+    fn malformed(&self) -> SkipReason {
+        if self.sniffed {
+            SkipReason::Binary
+        } else {
+            SkipReason::Encoding
+        }
+    }
+}
+
+/// Why a file couldn't be read as text.
+#[derive(Debug)]
+pub(crate) enum ReadError {
+    Io(io::Error),
+    Skip(SkipReason),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<SkipReason> for ReadError {
+    fn from(reason: SkipReason) -> Self {
+        Self::Skip(reason)
+    }
+}
+
+/// What [`Lines::next`] read.
+pub(crate) enum Next {
+    Line,
+    /// A part of a line longer than [`MAX_LINE`], the rest of which follows.
+    Part,
+    End,
+    /// The file turned out not to be UTF-8, its lines start over as Windows-1252.
+    Restart,
+}
+
+/// The lines of a file, decoded a chunk at a time in the encoding picked as described in
+/// [`detect`], so only the current line is kept in memory.
+pub(crate) struct Lines<R> {
+    reader: R,
+    detected: Detected,
+    decoder: Decoder,
+    /// Decoded text that hasn't been returned as lines yet, from `start` on.
+    pending: String,
+    start: usize,
+    eof: bool,
+}
+
+impl<R: BufRead + Seek> Lines<R> {
+    pub(crate) fn new(
+        mut reader: R,
+        encoding: Option<&'static Encoding>,
+    ) -> Result<Self, ReadError> {
+        let mut head = Vec::new();
+        reader
+            .by_ref()
+            .take(SNIFF_LEN.max(skip::BINARY_PREFIX) as u64)
+            .read_to_end(&mut head)?;
+        reader.rewind()?;
+        let detected = detect(&head, encoding)?;
+        Ok(Self {
+            reader,
+            decoder: detected
+                .encoding
+                .unwrap_or(UTF_8)
+                .new_decoder_with_bom_removal(),
+            detected,
+            pending: String::new(),
+            start: 0,
+            eof: false,
+        })
+    }
+
+    /// Starts over at the first line.
+    pub(crate) fn rewind(&mut self) -> io::Result<()> {
+        self.reader.rewind()?;
+        self.decoder = self
+            .detected
+            .encoding
+            .unwrap_or(UTF_8)
+            .new_decoder_with_bom_removal();
+        self.pending.clear();
+        self.start = 0;
+        self.eof = false;
+        Ok(())
+    }
+
+    /// The last `len` bytes of the file decoded, replacing malformed text, or `None` if the
+    /// file isn't longer than that. `len` has to be even, so UTF-16 isn't read from the
+    /// middle of a character. Call [`Lines::rewind`] to read lines afterwards.
+    pub(crate) fn tail(&mut self, len: u64) -> io::Result<Option<String>> {
+        let size = self.reader.seek(SeekFrom::End(0))?;
+        if size <= len {
+            return Ok(None);
+        }
+        self.reader.seek(SeekFrom::Start(size - len))?;
+        let mut bytes = Vec::new();
+        self.reader.by_ref().take(len).read_to_end(&mut bytes)?;
+        let encoding = self.detected.encoding.unwrap_or(UTF_8);
+        Ok(Some(
+            encoding.decode_without_bom_handling(&bytes).0.into_owned(),
+        ))
+    }
+
+    /// Reads the next line into `line`, without its line ending, splitting lines like
+    /// [`str::lines`] does. Lines longer than [`MAX_LINE`] are read a part at a time.
+    pub(crate) fn next(&mut self, line: &mut String) -> Result<Next, ReadError> {
+        loop {
+            let rest = &self.pending[self.start..];
+            if let Some(end) = rest.find('\n') {
+                let text = &rest[..end];
+                line.clear();
+                line.push_str(text.strip_suffix('\r').unwrap_or(text));
+                self.start += end + 1;
+                return Ok(Next::Line);
+            }
+            if rest.len() >= MAX_LINE {
+                let mut end = MAX_LINE;
+                while !rest.is_char_boundary(end) {
+                    end -= 1;
+                }
+                // A `\r` may be part of the line ending, it goes with the rest of the line.
+                let end = end - usize::from(rest[..end].ends_with('\r'));
+                line.clear();
+                line.push_str(&rest[..end]);
+                self.start += end;
+                return Ok(Next::Part);
+            }
+            if self.eof {
+                if rest.is_empty() {
+                    return Ok(Next::End);
+                }
+                line.clear();
+                line.push_str(rest);
+                self.start = self.pending.len();
+                return Ok(Next::Line);
+            }
+            self.pending.drain(..self.start);
+            self.start = 0;
+            if !self.fill()? {
+                self.detected.encoding = Some(WINDOWS_1252);
+                self.rewind()?;
+                return Ok(Next::Restart);
+            }
+        }
+    }
+
+    /// Decodes the next chunk of the file into `pending`. Returns false if a file guessed to
+    /// be UTF-8 isn't.
+    fn fill(&mut self) -> Result<bool, ReadError> {
+        let chunk = self.reader.fill_buf()?;
+        let last = chunk.is_empty();
+        if let Some(len) = self
+            .decoder
+            .max_utf8_buffer_length_without_replacement(chunk.len())
+        {
+            self.pending.reserve(len);
+        }
+        let (result, read) =
+            self.decoder
+                .decode_to_string_without_replacement(chunk, &mut self.pending, last);
+        self.reader.consume(read);
+        match result {
+            DecoderResult::InputEmpty => self.eof = last,
+            DecoderResult::OutputFull => {}
+            DecoderResult::Malformed(..) if self.detected.encoding.is_none() => return Ok(false),
+            DecoderResult::Malformed(..) => return Err(self.detected.malformed().into()),
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use super::*;
+
+    fn utf16(src: &str, encoding: &'static Encoding) -> Vec<u8> {
+        src.encode_utf16()
+            .flat_map(|unit| match encoding == UTF_16LE {
+                true => unit.to_le_bytes(),
+                false => unit.to_be_bytes(),
+            })
+            .collect()
+    }
+
+    /// Every line and part read from `bytes`, starting over on restarts.
+    fn read(bytes: &[u8], capacity: usize) -> Result<(Vec<String>, bool), ReadError> {
+        let reader = BufReader::with_capacity(capacity, Cursor::new(bytes));
+        let mut lines = Lines::new(reader, None)?;
+        let (mut read, mut restarted) = (Vec::new(), false);
+        let mut line = String::new();
+        loop {
+            match lines.next(&mut line)? {
+                Next::Line => read.push(line.clone()),
+                Next::Part => read.push(format!("{line}…")),
+                Next::End => return Ok((read, restarted)),
+                Next::Restart => {
+                    read.clear();
+                    restarted = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sniffs_utf16_with_a_few_nuls_in_the_other_lane() {
+        let src = "fn main() {\n    let a = 'Ā';\n}\n";
+        assert_eq!(sniff_utf16(&utf16(src, UTF_16LE)), Some(UTF_16LE));
+        assert_eq!(sniff_utf16(&utf16(src, UTF_16BE)), Some(UTF_16BE));
+        assert_eq!(sniff_utf16(src.as_bytes()), None);
+        assert_eq!(sniff_utf16(&[0; 64]), None);
+    }
+
+    #[test]
+    fn reads_utf16_lines() {
+        let bytes = utf16("fn a() {}\r\n'Ā'\n", UTF_16BE);
+        let lines = vec!["fn a() {}".to_string(), "'Ā'".to_string()];
+        assert_eq!(read(&bytes, 8000).unwrap(), (lines, false));
+    }
+
+    #[test]
+    fn restarts_as_windows_1252_after_invalid_utf8() {
+        let mut bytes = "a\n".repeat(5000).into_bytes();
+        bytes.extend(b"caf\xe9\n");
+        let (lines, restarted) = read(&bytes, 8000).unwrap();
+        assert!(restarted);
+        assert_eq!(lines.len(), 5001);
+        assert_eq!(lines[5000], "café");
+    }
+
+    #[test]
+    fn skips_binary_and_malformed_utf16() {
+        assert!(matches!(
+            read(b"a\0b\n", 8000),
+            Err(ReadError::Skip(SkipReason::Binary))
+        ));
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(utf16("a", UTF_16LE));
+        bytes.extend([0x00, 0xd8, b'b', 0]);
+        assert!(matches!(
+            read(&bytes, 8000),
+            Err(ReadError::Skip(SkipReason::Encoding))
+        ));
+    }
+
+    #[test]
+    fn tail_decodes_the_end_of_the_file() {
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(utf16(&"a\n".repeat(100), UTF_16LE));
+        let mut lines = Lines::new(Cursor::new(&bytes), None).unwrap();
+        assert_eq!(lines.tail(8).unwrap().as_deref(), Some("a\na\n"));
+        assert_eq!(lines.tail(1000).unwrap(), None);
+    }
+
+    #[test]
+    fn reads_long_lines_in_parts() {
+        // The first part ends right before the `\r` of the line ending.
+        let y = "y".repeat(MAX_LINE - 1);
+        let x = "x".repeat(2 * MAX_LINE);
+        let src = format!("{y}\r\n{x}0123456789\nend");
+        let (lines, _) = read(src.as_bytes(), 1024).unwrap();
+        assert_eq!(lines[..2], [format!("{y}…"), String::new()]);
+        assert!(lines.len() > 4);
+        assert!(lines
+            .iter()
+            .all(|line| line.len() < MAX_LINE + 1024 + '…'.len_utf8()));
+        let joined = lines.join("\n").replace("…\n", "");
+        assert_eq!(joined, format!("{y}\n{x}0123456789\nend"));
+    }
+}
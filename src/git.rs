@@ -0,0 +1,357 @@
+//! Counting the files of a git revision, read straight from the object database without
+//! checking the revision out.
+
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, HashMap},
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use git2::{FileMode, ObjectType, Oid, Repository, Tree, TreeWalkMode, TreeWalkResult};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::{
+    collect, count_bytes, skip_dir, CountResult, FileCount, Options, SkipReason, Skipped, Walked,
+    LCIGNORE,
+};
+
+/// The counts of blobs already counted, or why they were skipped, keyed by the blob and the
+/// file name since both decide the language.
+pub(crate) type BlobCache = HashMap<(Oid, OsString), Result<FileCount, SkipReason>>;
+
+/// The ignore files committed in a tree that are honored according to `options`, from
+/// highest to lowest precedence.
+fn ignore_files(options: &Options) -> Vec<&'static str> {
+    [
+        (options.lcignore, LCIGNORE),
+        (options.dot_ignore, ".ignore"),
+        (options.gitignore, ".gitignore"),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, name)| enabled.then_some(name))
+    .collect()
+}
+
+/// The path of `path` relative to the root of the working directory of `repo`.
+pub(crate) fn repo_relative(repo: &Repository, path: &Path) -> Result<PathBuf, git2::Error> {
+    let Some(workdir) = repo.workdir() else {
+        // A bare repository has no files on disk, so the path can only be its root.
+        return Ok(PathBuf::new());
+    };
+    let canonical =
+        |path: &Path| fs::canonicalize(path).map_err(|err| git2::Error::from_str(&err.to_string()));
+    canonical(path)?
+        .strip_prefix(canonical(workdir)?)
+        .map(Path::to_path_buf)
+        .map_err(|_| {
+            git2::Error::from_str(&format!(
+                "{} is outside of the repository's working directory",
+                path.display()
+            ))
+        })
+}
+
+/// The ignore rules committed in a tree, keyed by the directory they apply to.
+#[derive(Default)]
+struct Ignores {
+    /// Rules of each directory, with ignore files in the order of [`ignore_files`].
+    dirs: Vec<(PathBuf, Vec<(usize, Gitignore)>)>,
+}
+
+impl Ignores {
+    fn add(&mut self, dir: PathBuf, precedence: usize, src: &str) {
+        let mut builder = GitignoreBuilder::new(&dir);
+        for line in src.lines() {
+            // Invalid globs are skipped, like git does.
+            let _ = builder.add_line(None, line);
+        }
+        let Ok(gitignore) = builder.build() else {
+            return;
+        };
+        match self.dirs.iter_mut().find(|(d, _)| *d == dir) {
+            Some((_, rules)) => {
+                rules.push((precedence, gitignore));
+                rules.sort_by_key(|(precedence, _)| *precedence);
+            }
+            None => self.dirs.push((dir, vec![(precedence, gitignore)])),
+        }
+    }
+
+    /// Whether the file at `path` is ignored. Rules in deeper directories take precedence
+    /// over those of their parents.
+    fn is_ignored(&self, path: &Path) -> bool {
+        let mut dirs: Vec<_> = self
+            .dirs
+            .iter()
+            .filter(|(dir, _)| path.starts_with(dir))
+            .collect();
+        dirs.sort_by_key(|(dir, _)| Reverse(dir.components().count()));
+        for (_, rules) in dirs {
+            for (_, gitignore) in rules {
+                let matched = gitignore.matched_path_or_any_parents(path, false);
+                if !matched.is_none() {
+                    return matched.is_ignore();
+                }
+            }
+        }
+        false
+    }
+}
+
+/// A file found in a tree.
+pub(crate) struct TreeFile {
+    /// The path relative to the root of the repository.
+    pub path: PathBuf,
+    /// The path presented, as if the file was walked from the path counted on disk.
+    pub display: PathBuf,
+    pub oid: Oid,
+}
+
+/// The files under `prefix` in `tree`, skipping hidden and build directories, anything
+/// ignored by the ignore files committed in the tree and anything filtered out by the globs
+/// of `options`. Files are presented as if they were walked from `path` on disk, which is
+/// `prefix` in the working directory.
+pub(crate) fn tree_files(
+    repo: &Repository,
+    tree: &Tree,
+    prefix: &Path,
+    path: &Path,
+    options: &Options,
+) -> Result<Vec<TreeFile>, git2::Error> {
+    let ignore_names = ignore_files(options);
+    let display = |entry_path: &Path| {
+        let relative = entry_path.strip_prefix(prefix).unwrap();
+        if relative.as_os_str().is_empty() {
+            path.to_path_buf()
+        } else {
+            path.join(relative)
+        }
+    };
+
+    let mut files = Vec::new();
+    let mut ignores = Ignores::default();
+    let mut err = None;
+    tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        let name = entry.name().unwrap_or_default();
+        let entry_path = Path::new(dir).join(name);
+        let on_path = prefix.starts_with(&entry_path);
+        let under_path = entry_path.starts_with(prefix);
+        match entry.kind() {
+            Some(ObjectType::Tree) => {
+                if on_path
+                    || (under_path
+                        && !skip_dir(name)
+                        && !options
+                            .globs
+                            .matched(display(&entry_path), true)
+                            .is_ignore())
+                {
+                    TreeWalkResult::Ok
+                } else {
+                    TreeWalkResult::Skip
+                }
+            }
+            // Symbolic links aren't followed, like on disk, and their targets aren't code.
+            Some(ObjectType::Blob) if entry.filemode() == i32::from(FileMode::Link) => {
+                TreeWalkResult::Ok
+            }
+            Some(ObjectType::Blob) => {
+                if let Some(precedence) = ignore_names.iter().position(|n| *n == name) {
+                    match repo.find_blob(entry.id()) {
+                        Ok(blob) => {
+                            let src = String::from_utf8_lossy(blob.content());
+                            ignores.add(PathBuf::from(dir), precedence, &src);
+                        }
+                        Err(e) => {
+                            err = Some(e);
+                            return TreeWalkResult::Abort;
+                        }
+                    }
+                }
+                let display = under_path.then(|| display(&entry_path));
+                if let Some(display) = display {
+                    if !options.globs.matched(&display, false).is_ignore() {
+                        files.push(TreeFile {
+                            path: entry_path,
+                            display,
+                            oid: entry.id(),
+                        });
+                    }
+                }
+                TreeWalkResult::Ok
+            }
+            // Submodules aren't part of this repository.
+            _ => TreeWalkResult::Ok,
+        }
+    })?;
+    if let Some(err) = err {
+        return Err(err);
+    }
+
+    files.retain(|file| !ignores.is_ignored(&file.path));
+    Ok(files)
+}
+
+/// Counts the files under `prefix` in `tree`, see [`tree_files`].
+pub(crate) fn count_tree(
+    repo: &Repository,
+    tree: &Tree,
+    prefix: &Path,
+    path: &Path,
+    options: &Options,
+    cache: &mut BlobCache,
+) -> Result<Vec<Walked>, git2::Error> {
+    let mut files = Vec::new();
+    for TreeFile {
+        path: entry_path,
+        display,
+        oid,
+    } in tree_files(repo, tree, prefix, path, options)?
+    {
+        if !options.may_count(&entry_path) {
+            continue;
+        }
+        let name = entry_path.file_name().unwrap_or_default().to_os_string();
+        let counted = match cache.entry((oid, name)) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => {
+                let blob = repo.find_blob(oid)?;
+                entry
+                    .insert(count_bytes(&display, blob.content(), options))
+                    .clone()
+            }
+        };
+        match counted {
+            Ok(file) if options.counts_language(&file.language) => {
+                files.push(Walked::Counted(FileCount {
+                    path: display,
+                    ..file
+                }));
+            }
+            Ok(_) => {}
+            Err(reason) => files.push(Walked::Skipped(Skipped {
+                path: display,
+                reason,
+            })),
+        }
+    }
+    Ok(files)
+}
+
+/// Counts the lines of every file under each of `paths` as of the revision `rev` of the
+/// git repository containing them, without checking that revision out.
+///
+/// Like [`crate::count_paths`] hidden and build directories are skipped. The `.gitignore`,
+/// `.ignore` and `.lcignore` files committed at that revision are honored according to
+/// `options`, while `.git/info/exclude` and global excludes aren't.
+pub fn count_rev(
+    paths: &[impl AsRef<Path>],
+    rev: &str,
+    options: &Options,
+) -> Result<CountResult, git2::Error> {
+    let mut files = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        let repo = Repository::discover(path)?;
+        let tree = repo.revparse_single(rev)?.peel_to_tree()?;
+        let prefix = repo_relative(&repo, path)?;
+        files.extend(count_tree(
+            &repo,
+            &tree,
+            &prefix,
+            path,
+            options,
+            &mut BlobCache::new(),
+        )?);
+    }
+    Ok(collect(files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{globs, TestRepo};
+
+    fn paths(res: &CountResult) -> Vec<&str> {
+        res.files
+            .iter()
+            .map(|file| file.path.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn honors_committed_ignore_files() {
+        let repo = TestRepo::new("ignore");
+        repo.commit(
+            &[
+                (".gitignore", "gen/\n*.txt\n"),
+                ("sub/.gitignore", "!keep.txt\n"),
+                ("a.rs", "fn a() {}\n"),
+                ("gen/b.rs", "fn b() {}\n"),
+                ("c.txt", "c\n"),
+                ("sub/keep.txt", "k\n"),
+                ("sub/d.txt", "d\n"),
+            ],
+            0,
+        );
+        // Files that aren't committed aren't counted.
+        fs::write(repo.path().join("e.rs"), "fn e() {}\n").unwrap();
+
+        let res = repo.count("HEAD", &Options::default());
+        assert_eq!(
+            paths(&res),
+            [".gitignore", "a.rs", "sub/.gitignore", "sub/keep.txt"]
+        );
+        let options = Options {
+            gitignore: false,
+            ..Options::default()
+        };
+        assert_eq!(paths(&repo.count("HEAD", &options)).len(), 7);
+    }
+
+    #[test]
+    fn filters_files_by_glob_and_language() {
+        let repo = TestRepo::new("rev-filters");
+        repo.commit(
+            &[
+                ("a.rs", "fn a() {}\n"),
+                ("b.py", "b = 1\n"),
+                ("c.xyz", "c\n"),
+                ("tests/d.rs", "fn d() {}\n"),
+            ],
+            0,
+        );
+
+        let options = Options {
+            globs: globs(repo.path(), &["**/*.rs"], &["tests/"]),
+            ..Options::default()
+        };
+        assert_eq!(paths(&repo.count("HEAD", &options)), ["a.rs"]);
+        let options = Options {
+            include_languages: vec!["rust".to_string(), "XYZ".to_string()],
+            exclude_languages: vec!["RUST".to_string()],
+            ..Options::default()
+        };
+        assert_eq!(paths(&repo.count("HEAD", &options)), ["c.xyz"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_committed_symbolic_links() {
+        let repo = TestRepo::new("rev-links");
+        std::os::unix::fs::symlink("a.rs", repo.path().join("b.rs")).unwrap();
+        std::os::unix::fs::symlink("../../etc/passwd", repo.path().join("c.txt")).unwrap();
+        let mut index = repo.repo.index().unwrap();
+        for path in ["b.rs", "c.txt"] {
+            index.add_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        repo.commit(&[("a.rs", "fn a() {}\n")], 0);
+
+        let on_disk = crate::count_path(repo.path(), &Options::default());
+        assert_eq!(on_disk.files.len(), 1);
+        assert_eq!(paths(&repo.count("HEAD", &Options::default())), ["a.rs"]);
+    }
+}
@@ -0,0 +1,152 @@
+//! Line counts over the history of a git repository.
+
+use std::{collections::HashMap, path::Path};
+
+use git2::{Oid, Repository, Sort};
+
+use crate::{
+    collect,
+    git::{count_tree, repo_relative, BlobCache},
+    CountResult, Options,
+};
+
+/// Which commits of the history to count.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Sample {
+    /// Every commit.
+    #[default]
+    Commit,
+    /// The last commit of each day, in the committer's time zone.
+    Day,
+    /// The last commit of each week, with weeks starting on Monday.
+    Week,
+    /// Every tagged commit.
+    Tag,
+}
+
+/// The lines counted as of a single commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The id of the commit.
+    pub commit: String,
+    /// The commit time in seconds since the Unix epoch.
+    pub time: i64,
+    /// The offset of the committer's time zone from UTC in minutes.
+    pub offset: i32,
+    /// The tags pointing at the commit.
+    pub tags: Vec<String>,
+    pub result: CountResult,
+}
+
+/// The names of the tags pointing at each commit, sorted.
+fn tags(repo: &Repository) -> Result<HashMap<Oid, Vec<String>>, git2::Error> {
+    let mut tags = HashMap::<_, Vec<_>>::new();
+    for reference in repo.references_glob("refs/tags/*")? {
+        let reference = reference?;
+        // Tags of trees and blobs don't point at a commit.
+        let Ok(commit) = reference.peel_to_commit() else {
+            continue;
+        };
+        let name = reference.shorthand().unwrap_or_default().to_string();
+        tags.entry(commit.id()).or_default().push(name);
+    }
+    for names in tags.values_mut() {
+        names.sort();
+    }
+    Ok(tags)
+}
+
+/// Counts the lines of every file under `path` at the commits of `rev` chosen by `sample`,
+/// from oldest to newest.
+///
+/// Only the first parent of merges is followed, so the history is that of the branch
+/// itself. Files are counted like [`crate::count_rev`] does, but each blob is counted only
+/// once however many commits contain it.
+pub fn history(
+    path: &Path,
+    rev: &str,
+    sample: Sample,
+    options: &Options,
+) -> Result<Vec<Snapshot>, git2::Error> {
+    let repo = Repository::discover(path)?;
+    let prefix = repo_relative(&repo, path)?;
+    let tags = tags(&repo)?;
+
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    walk.push(repo.revparse_single(rev)?.peel_to_commit()?.id())?;
+    walk.simplify_first_parent()?;
+
+    // The walk goes from newest to oldest, so the first commit of a day or week is its last.
+    let mut commits = Vec::new();
+    let mut last_period = None;
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        let time = commit.time();
+        let day = (time.seconds() + i64::from(time.offset_minutes()) * 60).div_euclid(86400);
+        let period = match sample {
+            Sample::Commit => None,
+            Sample::Tag if !tags.contains_key(&commit.id()) => continue,
+            Sample::Tag => None,
+            Sample::Day => Some(day),
+            // The epoch was a Thursday.
+            Sample::Week => Some((day + 3).div_euclid(7)),
+        };
+        if period.is_some() && period == last_period {
+            continue;
+        }
+        last_period = period;
+        commits.push(commit);
+    }
+
+    let mut cache = BlobCache::new();
+    commits
+        .iter()
+        .rev()
+        .map(|commit| {
+            let files = count_tree(&repo, &commit.tree()?, &prefix, path, options, &mut cache)?;
+            Ok(Snapshot {
+                commit: commit.id().to_string(),
+                time: commit.time().seconds(),
+                offset: commit.time().offset_minutes(),
+                tags: tags.get(&commit.id()).cloned().unwrap_or_default(),
+                result: collect(files),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::TestRepo, Stats};
+
+    #[test]
+    fn samples_history() {
+        const DAY: i64 = 86400;
+        let repo = TestRepo::new("history");
+        repo.commit(&[("a.rs", "fn a() {}\n")], DAY);
+        repo.commit(&[("b.rs", "fn b() {}\n")], DAY + 60);
+        repo.commit(&[("a.rs", "fn a() {}\nfn c() {}\n")], 2 * DAY);
+        let snapshots = |sample| history(repo.path(), "HEAD", sample, &Options::default()).unwrap();
+        let totals = |sample| {
+            snapshots(sample)
+                .iter()
+                .map(|snapshot| (snapshot.time, snapshot.result.total.code))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            totals(Sample::Commit),
+            [(DAY, 1), (DAY + 60, 2), (2 * DAY, 3)]
+        );
+        assert_eq!(totals(Sample::Day), [(DAY + 60, 2), (2 * DAY, 3)]);
+        assert_eq!(totals(Sample::Week), [(2 * DAY, 3)]);
+        assert_eq!(
+            snapshots(Sample::Commit)[2].result.languages["Rust"],
+            Stats {
+                code: 3,
+                ..Stats::default()
+            }
+        );
+    }
+}
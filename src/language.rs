@@ -0,0 +1,573 @@
+use std::{collections::HashMap, path::Path};
+
+use phf::phf_map;
+
+/// How a comment is written in a language.
+#[derive(Debug)]
+pub enum CommentSyntax<'a> {
+    /// A comment that starts with the given token and runs to the end of the line.
+    LineStart(&'a str),
+    /// A comment delimited by a start and end token, possibly spanning several lines.
+    Range(&'a str, &'a str),
+    /// Like [`CommentSyntax::Range`], but comments can be nested inside each other.
+    Nested(&'a str, &'a str),
+    /// Like [`CommentSyntax::LineStart`], but for documentation comments. A comment like
+    /// `////` where a start token ending in `/` is followed by another `/` is not a
+    /// documentation comment.
+    DocLineStart(&'a str),
+    /// Like [`CommentSyntax::Range`], but for documentation comments. A comment like `/**/`
+    /// where the end token overlaps the start token is not a documentation comment.
+    DocRange(&'a str, &'a str),
+    /// Like [`CommentSyntax::DocRange`], but comments can be nested inside it like in a
+    /// [`CommentSyntax::Nested`] comment with the same end.
+    NestedDoc(&'a str, &'a str),
+}
+
+/// How a string or character literal is written in a language. Comment tokens inside
+/// literals are ignored.
+#[derive(Debug)]
+pub enum StringSyntax<'a> {
+    /// A string delimited by a start and end token in which `\` escapes the next character.
+    Escaped(&'a str, &'a str),
+    /// A string delimited by a start and end token without any escapes.
+    Verbatim(&'a str, &'a str),
+    /// A character literal like `'a'` or `'\n'`. A lone `'`, as in Rust lifetimes, isn't
+    /// treated as the start of a literal.
+    Char,
+    /// A Rust raw string like `r"..."` or `br#"..."#`.
+    RawRust,
+    /// A string with `\` escapes that's documentation rather than code when it starts a
+    /// line, like Python docstrings. Code following the end of the string on the same line
+    /// still makes that line count as code.
+    Doc(&'a str, &'a str),
+}
+
+/// How code in another language is embedded in a language.
+#[derive(Debug)]
+pub enum EmbedSyntax<'a> {
+    /// The lines between a line starting with the given HTML element, like `<script>`, and
+    /// the line closing it. They're in the language with the given extension unless the
+    /// element's `lang` or `type` attribute names another.
+    Element(&'a str, &'a str),
+    /// Markdown code blocks, fenced by lines of three or more backticks or tildes, in the
+    /// language named by the first word after the opening fence.
+    Fence,
+}
+
+const C_STYLE: &[CommentSyntax] = &[
+    CommentSyntax::LineStart("//"),
+    CommentSyntax::Range("/*", "*/"),
+    CommentSyntax::DocRange("/**", "*/"),
+];
+/// C style comments with the documentation comments used by Doxygen.
+const DOXYGEN: &[CommentSyntax] = &[
+    CommentSyntax::LineStart("//"),
+    CommentSyntax::Range("/*", "*/"),
+    CommentSyntax::DocLineStart("///"),
+    CommentSyntax::DocLineStart("//!"),
+    CommentSyntax::DocRange("/**", "*/"),
+    CommentSyntax::DocRange("/*!", "*/"),
+];
+const C_NESTED: &[CommentSyntax] = &[
+    CommentSyntax::LineStart("//"),
+    CommentSyntax::Nested("/*", "*/"),
+    CommentSyntax::DocLineStart("///"),
+    CommentSyntax::NestedDoc("/**", "*/"),
+];
+const RUST: &[CommentSyntax] = &[
+    CommentSyntax::LineStart("//"),
+    CommentSyntax::Nested("/*", "*/"),
+    CommentSyntax::DocLineStart("///"),
+    CommentSyntax::DocLineStart("//!"),
+    CommentSyntax::NestedDoc("/**", "*/"),
+    CommentSyntax::NestedDoc("/*!", "*/"),
+];
+const HASH: &[CommentSyntax] = &[CommentSyntax::LineStart("#")];
+const HTML: &[CommentSyntax] = &[CommentSyntax::Range("<!--", "-->")];
+/// Lua comments, with long brackets of up to three `=`.
+const LUA: &[CommentSyntax] = &[
+    CommentSyntax::LineStart("--"),
+    CommentSyntax::Range("--[[", "]]"),
+    CommentSyntax::Range("--[=[", "]=]"),
+    CommentSyntax::Range("--[==[", "]==]"),
+    CommentSyntax::Range("--[===[", "]===]"),
+];
+const MATLAB: &[CommentSyntax] = &[
+    CommentSyntax::LineStart("%"),
+    CommentSyntax::Range("%{", "}%"),
+];
+const SCHEME: &[CommentSyntax] = &[
+    CommentSyntax::LineStart(";"),
+    CommentSyntax::Nested("#|", "|#"),
+];
+
+const C_STRINGS: &[StringSyntax] = &[StringSyntax::Escaped("\"", "\""), StringSyntax::Char];
+const RUST_STRINGS: &[StringSyntax] = &[
+    StringSyntax::Escaped("\"", "\""),
+    StringSyntax::Char,
+    StringSyntax::RawRust,
+];
+const JS_STRINGS: &[StringSyntax] = &[
+    StringSyntax::Escaped("\"", "\""),
+    StringSyntax::Escaped("'", "'"),
+    StringSyntax::Escaped("`", "`"),
+];
+const TRIPLE_QUOTE_STRINGS: &[StringSyntax] = &[
+    StringSyntax::Escaped("\"", "\""),
+    StringSyntax::Escaped("\"\"\"", "\"\"\""),
+    StringSyntax::Char,
+];
+const QUOTE_STRINGS: &[StringSyntax] = &[
+    StringSyntax::Escaped("\"", "\""),
+    StringSyntax::Escaped("'", "'"),
+];
+/// Lua strings, with long brackets of up to three `=`.
+const LUA_STRINGS: &[StringSyntax] = &[
+    StringSyntax::Escaped("\"", "\""),
+    StringSyntax::Escaped("'", "'"),
+    StringSyntax::Verbatim("[[", "]]"),
+    StringSyntax::Verbatim("[=[", "]=]"),
+    StringSyntax::Verbatim("[==[", "]==]"),
+    StringSyntax::Verbatim("[===[", "]===]"),
+];
+const PYTHON_STRINGS: &[StringSyntax] = &[
+    StringSyntax::Escaped("\"", "\""),
+    StringSyntax::Escaped("'", "'"),
+    StringSyntax::Escaped("\"\"\"", "\"\"\""),
+    StringSyntax::Escaped("'''", "'''"),
+];
+/// Python strings, with docstrings listed first to take precedence at the start of a line.
+const PYTHON_DOC_STRINGS: &[StringSyntax] = &[
+    StringSyntax::Doc("\"\"\"", "\"\"\""),
+    StringSyntax::Doc("'''", "'''"),
+    StringSyntax::Doc("r\"\"\"", "\"\"\""),
+    StringSyntax::Doc("r'''", "'''"),
+    StringSyntax::Escaped("\"", "\""),
+    StringSyntax::Escaped("'", "'"),
+    StringSyntax::Escaped("\"\"\"", "\"\"\""),
+    StringSyntax::Escaped("'''", "'''"),
+];
+const ELIXIR_STRINGS: &[StringSyntax] = &[
+    StringSyntax::Escaped("\"", "\""),
+    StringSyntax::Escaped("'", "'"),
+    StringSyntax::Escaped("\"\"\"", "\"\"\""),
+    StringSyntax::Doc("@moduledoc \"\"\"", "\"\"\""),
+    StringSyntax::Doc("@moduledoc \"", "\""),
+    StringSyntax::Doc("@doc \"\"\"", "\"\"\""),
+    StringSyntax::Doc("@doc \"", "\""),
+    StringSyntax::Doc("@typedoc \"\"\"", "\"\"\""),
+    StringSyntax::Doc("@typedoc \"", "\""),
+];
+const SHELL_STRINGS: &[StringSyntax] = &[
+    StringSyntax::Escaped("\"", "\""),
+    StringSyntax::Verbatim("'", "'"),
+];
+const TOML_STRINGS: &[StringSyntax] = &[
+    StringSyntax::Escaped("\"", "\""),
+    StringSyntax::Verbatim("'", "'"),
+    StringSyntax::Escaped("\"\"\"", "\"\"\""),
+    StringSyntax::Verbatim("'''", "'''"),
+];
+const DOUBLE_QUOTE_STRINGS: &[StringSyntax] = &[StringSyntax::Escaped("\"", "\"")];
+
+const C_BRANCHES: &[&str] = &["if", "for", "foreach", "while", "case", "catch", "&&", "||"];
+const RUST_BRANCHES: &[&str] = &["if", "for", "while", "loop", "=>", "?", "&&", "||"];
+const PYTHON_BRANCHES: &[&str] = &["if", "elif", "for", "while", "except", "case", "and", "or"];
+const RUBY_BRANCHES: &[&str] = &[
+    "if", "elsif", "unless", "for", "while", "until", "when", "rescue", "and", "or", "&&", "||",
+];
+const PERL_BRANCHES: &[&str] = &[
+    "if", "elsif", "unless", "for", "foreach", "while", "until", "and", "or", "&&", "||",
+];
+const PHP_BRANCHES: &[&str] = &[
+    "if", "elseif", "for", "foreach", "while", "case", "catch", "and", "or", "&&", "||",
+];
+const SHELL_BRANCHES: &[&str] = &["if", "elif", "for", "while", "until", ";;", "&&", "||"];
+const LUA_BRANCHES: &[&str] = &["if", "elseif", "for", "while", "repeat", "and", "or"];
+const HASKELL_BRANCHES: &[&str] = &["if", "case", "&&", "||"];
+const ELIXIR_BRANCHES: &[&str] = &[
+    "if", "unless", "case", "cond", "with", "and", "or", "&&", "||",
+];
+const ZIG_BRANCHES: &[&str] = &["if", "for", "while", "catch", "orelse", "and", "or"];
+const MATLAB_BRANCHES: &[&str] = &["if", "elseif", "for", "while", "case", "catch", "&&", "||"];
+const SCHEME_BRANCHES: &[&str] = &["if", "cond", "case", "when", "unless", "and", "or"];
+
+/// A language the counter knows the comment and string syntax of.
+#[derive(Debug)]
+pub struct Language<'a> {
+    pub name: &'a str,
+    pub comments: &'a [CommentSyntax<'a>],
+    pub strings: &'a [StringSyntax<'a>],
+    /// Keywords and operators that branch, counted outside of comments and strings to
+    /// estimate complexity. Keywords only match whole words.
+    pub branches: &'a [&'a str],
+    /// Whether files of the language get minified, like JavaScript does, so that very long
+    /// lines mark a file as minified.
+    pub minifiable: bool,
+    /// How code in other languages is embedded in the language's files.
+    pub embedded: &'a [EmbedSyntax<'a>],
+}
+
+impl<'a> Language<'a> {
+    pub const fn new(name: &'a str, comments: &'a [CommentSyntax<'a>]) -> Self {
+        Self {
+            name,
+            comments,
+            strings: &[],
+            branches: &[],
+            minifiable: false,
+            embedded: &[],
+        }
+    }
+
+    pub const fn with_strings(mut self, strings: &'a [StringSyntax<'a>]) -> Self {
+        self.strings = strings;
+        self
+    }
+
+    pub const fn with_branches(mut self, branches: &'a [&'a str]) -> Self {
+        self.branches = branches;
+        self
+    }
+
+    pub const fn minifiable(mut self) -> Self {
+        self.minifiable = true;
+        self
+    }
+
+    pub const fn with_embedded(mut self, embedded: &'a [EmbedSyntax<'a>]) -> Self {
+        self.embedded = embedded;
+        self
+    }
+}
+
+/// Scripts and style sheets in HTML pages and single-file components.
+const HTML_EMBEDDED: &[EmbedSyntax] = &[
+    EmbedSyntax::Element("script", "js"),
+    EmbedSyntax::Element("style", "css"),
+];
+
+/// Known languages, keyed by file extension. Extensions mapping to languages with the
+/// same name are counted together.
+pub static LANGUAGES: phf::Map<&'static str, Language<'static>> = phf_map! {
+    "rs" => Language::new("Rust", RUST).with_strings(RUST_STRINGS).with_branches(RUST_BRANCHES),
+    "go" => Language::new("Go", C_STYLE).with_strings(&[StringSyntax::Escaped("\"", "\""), StringSyntax::Verbatim("`", "`"), StringSyntax::Char]).with_branches(C_BRANCHES),
+    "h" => Language::new("C", DOXYGEN).with_strings(C_STRINGS).with_branches(C_BRANCHES),
+    "c" => Language::new("C", DOXYGEN).with_strings(C_STRINGS).with_branches(C_BRANCHES),
+    "hpp" => Language::new("C++", DOXYGEN).with_strings(C_STRINGS).with_branches(C_BRANCHES),
+    "cpp" => Language::new("C++", DOXYGEN).with_strings(C_STRINGS).with_branches(C_BRANCHES),
+    "cs" => Language::new("C#", DOXYGEN).with_strings(&[StringSyntax::Escaped("\"", "\""), StringSyntax::Verbatim("@\"", "\""), StringSyntax::Char]).with_branches(C_BRANCHES),
+    "java" => Language::new("Java", C_STYLE).with_strings(TRIPLE_QUOTE_STRINGS).with_branches(C_BRANCHES),
+    "js" => Language::new("javascript", C_STYLE).with_strings(JS_STRINGS).with_branches(C_BRANCHES).minifiable(),
+    "carbon" => Language::new("Carbon", C_STYLE).with_strings(TRIPLE_QUOTE_STRINGS).with_branches(C_BRANCHES),
+    "swift" => Language::new("Swift", C_NESTED).with_strings(TRIPLE_QUOTE_STRINGS).with_branches(C_BRANCHES),
+    "dart" => Language::new("Dart", C_NESTED).with_strings(PYTHON_STRINGS).with_branches(C_BRANCHES),
+    "sc" => Language::new("Scala", C_NESTED).with_strings(TRIPLE_QUOTE_STRINGS).with_branches(C_BRANCHES),
+    "kt" => Language::new("Kotlin", C_NESTED).with_strings(TRIPLE_QUOTE_STRINGS).with_branches(C_BRANCHES),
+    "hla" => Language::new("HLA", C_STYLE).with_strings(C_STRINGS),
+    "lua" => Language::new("Lua", LUA).with_strings(LUA_STRINGS).with_branches(LUA_BRANCHES),
+    "rhai" => Language::new("Rhai", C_NESTED).with_strings(JS_STRINGS).with_branches(C_BRANCHES),
+
+    "ts" => Language::new("TypeScript", C_STYLE).with_strings(JS_STRINGS).with_branches(C_BRANCHES),
+
+
+    "wgsl" => Language::new("wgsl", C_STYLE).with_branches(C_BRANCHES),
+    "glsl" => Language::new("glsl", C_STYLE).with_branches(C_BRANCHES),
+    "hlsl" => Language::new("hlsl", C_STYLE).with_strings(C_STRINGS).with_branches(C_BRANCHES),
+
+
+    "php" => Language::new("PHP", &[CommentSyntax::LineStart("//"), CommentSyntax::LineStart("#"), CommentSyntax::Range("/*", "*/"), CommentSyntax::DocRange("/**", "*/")]).with_strings(QUOTE_STRINGS).with_branches(PHP_BRANCHES),
+    "hs" => Language::new("Haskell", &[CommentSyntax::LineStart("--"), CommentSyntax::Nested("{-", "-}"), CommentSyntax::DocLineStart("-- |"), CommentSyntax::DocLineStart("-- ^"), CommentSyntax::NestedDoc("{-|", "-}")]).with_strings(C_STRINGS).with_branches(HASKELL_BRANCHES),
+    "rb" => Language::new("Ruby", &[CommentSyntax::LineStart("#"), CommentSyntax::Range("=begin", "=end")]).with_strings(QUOTE_STRINGS).with_branches(RUBY_BRANCHES),
+    "asm" => Language::new("Assembly", &[CommentSyntax::LineStart(";")]).with_strings(DOUBLE_QUOTE_STRINGS),
+    "tao" => Language::new("Tao", &[CommentSyntax::LineStart("##")]).with_strings(C_STRINGS),
+
+    "html" => Language::new("html", HTML).with_embedded(HTML_EMBEDDED).minifiable(),
+    "htm" => Language::new("html", HTML).with_embedded(HTML_EMBEDDED).minifiable(),
+    "vue" => Language::new("Vue", HTML).with_embedded(HTML_EMBEDDED),
+    "svelte" => Language::new("Svelte", HTML).with_embedded(HTML_EMBEDDED),
+    "md" => Language::new("Markdown", HTML).with_embedded(&[EmbedSyntax::Fence]),
+    "markdown" => Language::new("Markdown", HTML).with_embedded(&[EmbedSyntax::Fence]),
+    "css" => Language::new("css", &[CommentSyntax::Range("/*", "*/")]).with_strings(QUOTE_STRINGS).minifiable(),
+    "scss" => Language::new("SCSS", C_STYLE).with_strings(QUOTE_STRINGS),
+    "less" => Language::new("Less", C_STYLE).with_strings(QUOTE_STRINGS),
+    "zig" => Language::new("Zig", &[CommentSyntax::LineStart("//")]).with_strings(C_STRINGS).with_branches(ZIG_BRANCHES),
+
+    "py" => Language::new("Python", HASH).with_strings(PYTHON_DOC_STRINGS).with_branches(PYTHON_BRANCHES),
+    "r" => Language::new("R", &[CommentSyntax::LineStart("#"), CommentSyntax::DocLineStart("#'")]).with_strings(QUOTE_STRINGS).with_branches(C_BRANCHES),
+    "ex" => Language::new("Elixir", HASH).with_strings(ELIXIR_STRINGS).with_branches(ELIXIR_BRANCHES),
+    "exs" => Language::new("Elixir", HASH).with_strings(ELIXIR_STRINGS).with_branches(ELIXIR_BRANCHES),
+    "pl" => Language::new("Perl", HASH).with_strings(SHELL_STRINGS).with_branches(PERL_BRANCHES),
+    "emojic" => Language::new("emojicode", HASH).with_strings(DOUBLE_QUOTE_STRINGS),
+
+    "toml" => Language::new("TOML", HASH).with_strings(TOML_STRINGS),
+    "gitignore" => Language::new("git ignore", HASH),
+    "makefile" => Language::new("make file", HASH),
+    "mk" => Language::new("make file", HASH),
+    "bash" => Language::new("bash script", HASH).with_strings(SHELL_STRINGS).with_branches(SHELL_BRANCHES),
+    "sh" => Language::new("bash script", HASH).with_strings(SHELL_STRINGS).with_branches(SHELL_BRANCHES),
+    "zsh" => Language::new("bash script", HASH).with_strings(SHELL_STRINGS).with_branches(SHELL_BRANCHES),
+    "dockerfile" => Language::new("Dockerfile", HASH).with_strings(SHELL_STRINGS),
+    "cmake" => Language::new("CMake", &[CommentSyntax::LineStart("#"), CommentSyntax::Range("#[[", "]]")]).with_strings(DOUBLE_QUOTE_STRINGS),
+    "groovy" => Language::new("Groovy", C_STYLE).with_strings(PYTHON_STRINGS).with_branches(C_BRANCHES),
+    "gradle" => Language::new("Groovy", C_STYLE).with_strings(PYTHON_STRINGS).with_branches(C_BRANCHES),
+
+    "bat" => Language::new("batch script", &[CommentSyntax::LineStart("Rem"), CommentSyntax::LineStart("::")]),
+
+    "m" => Language::new("Matlab", MATLAB).with_strings(DOUBLE_QUOTE_STRINGS).with_branches(MATLAB_BRANCHES),
+    "mat" => Language::new("Matlab", MATLAB).with_strings(DOUBLE_QUOTE_STRINGS).with_branches(MATLAB_BRANCHES),
+
+    "ss" => Language::new("Scheme", SCHEME).with_strings(DOUBLE_QUOTE_STRINGS).with_branches(SCHEME_BRANCHES),
+    "sls" => Language::new("Scheme", SCHEME).with_strings(DOUBLE_QUOTE_STRINGS).with_branches(SCHEME_BRANCHES),
+    "scm" => Language::new("Scheme", SCHEME).with_strings(DOUBLE_QUOTE_STRINGS).with_branches(SCHEME_BRANCHES),
+};
+
+/// Files recognized by their exact name, mapped to the extension of their language in
+/// [`LANGUAGES`].
+static FILENAMES: phf::Map<&'static str, &'static str> = phf_map! {
+    "Makefile" => "makefile",
+    "makefile" => "makefile",
+    "GNUmakefile" => "makefile",
+    "Dockerfile" => "dockerfile",
+    "Containerfile" => "dockerfile",
+    "CMakeLists.txt" => "cmake",
+    "Jenkinsfile" => "groovy",
+    "Rakefile" => "rb",
+    "Gemfile" => "rb",
+    "Vagrantfile" => "rb",
+    ".bashrc" => "bash",
+    ".bash_profile" => "bash",
+    ".profile" => "bash",
+    ".zshrc" => "zsh",
+    "Cargo.lock" => "toml",
+};
+
+/// Interpreters named in shebangs and editor modes named in modelines, mapped to the
+/// extension of their language in [`LANGUAGES`].
+static INTERPRETERS: phf::Map<&'static str, &'static str> = phf_map! {
+    "python" => "py",
+    "perl" => "pl",
+    "ruby" => "rb",
+    "node" => "js",
+    "javascript" => "js",
+    "typescript" => "ts",
+    "deno" => "ts",
+    "lua" => "lua",
+    "php" => "php",
+    "rscript" => "r",
+    "elixir" => "ex",
+    "r" => "r",
+    "sh" => "bash",
+    "bash" => "bash",
+    "zsh" => "zsh",
+    "dash" => "bash",
+    "ksh" => "bash",
+    "make" => "makefile",
+    "makefile" => "makefile",
+    "runhaskell" => "hs",
+    "haskell" => "hs",
+    "rust" => "rs",
+    "c" => "c",
+    "cpp" => "cpp",
+    "c++" => "cpp",
+    "go" => "go",
+    "java" => "java",
+    "scheme" => "scm",
+    "guile" => "scm",
+    "matlab" => "m",
+    "toml" => "toml",
+    "groovy" => "groovy",
+    "dockerfile" => "dockerfile",
+    "cmake" => "cmake",
+};
+
+/// The language named by the interpreter in a shebang line like `#!/usr/bin/env python3`.
+fn from_shebang(line: &str) -> Option<&'static str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    // Strip versions, as in `python3.11`.
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS.get(&interpreter.to_lowercase()).copied()
+}
+
+/// The language named by a vim (`vim: set ft=python:`) or emacs (`-*- mode: python -*-`)
+/// modeline.
+fn from_modeline(line: &str) -> Option<&'static str> {
+    let mode = if let Some((_, rest)) = line.split_once("-*-") {
+        let rest = rest.split("-*-").next()?;
+        rest.split(';')
+            .find_map(|var| var.trim().strip_prefix("mode:"))
+            .unwrap_or(rest)
+    } else {
+        let (_, rest) = line.split_once("vim:").or_else(|| line.split_once("vi:"))?;
+        rest.split(|c: char| c == ':' || c.is_whitespace())
+            .find_map(|opt| {
+                opt.strip_prefix("ft=")
+                    .or_else(|| opt.strip_prefix("filetype="))
+                    .or_else(|| opt.strip_prefix("syntax="))
+            })?
+    };
+    INTERPRETERS.get(&mode.trim().to_lowercase()).copied()
+}
+
+/// How many lines at the start and end of a file are searched for a modeline.
+pub(crate) const MODELINE_LINES: usize = 5;
+
+/// The languages files are detected as: the built-in [`LANGUAGES`] along with any added
+/// at runtime, which take precedence over the built-in ones.
+#[derive(Debug, Clone, Default)]
+pub struct Languages {
+    extensions: HashMap<String, &'static Language<'static>>,
+    filenames: HashMap<String, &'static Language<'static>>,
+}
+
+impl Languages {
+    /// Adds a language for files with the given extensions and file names. If it has the
+    /// same name as a language that's already known it replaces that language, including
+    /// for that language's extensions and file names.
+    pub fn add(
+        &mut self,
+        language: &'static Language<'static>,
+        extensions: impl IntoIterator<Item = String>,
+        filenames: impl IntoIterator<Item = String>,
+    ) {
+        for (ext, lang) in LANGUAGES.entries() {
+            if lang.name == language.name {
+                self.extensions.insert(ext.to_string(), language);
+            }
+        }
+        for (name, ext) in FILENAMES.entries() {
+            if LANGUAGES[ext].name == language.name {
+                self.filenames.insert(name.to_string(), language);
+            }
+        }
+        for lang in self
+            .extensions
+            .values_mut()
+            .chain(self.filenames.values_mut())
+        {
+            if lang.name == language.name {
+                *lang = language;
+            }
+        }
+
+        for ext in extensions {
+            self.extensions.insert(ext.to_lowercase(), language);
+        }
+        for name in filenames {
+            self.filenames.insert(name, language);
+        }
+    }
+
+    /// Looks up a language by file extension, ignoring case.
+    pub fn from_extension(&self, ext: &str) -> Option<&'static Language<'static>> {
+        let lower = ext.to_lowercase();
+        self.extensions
+            .get(&lower)
+            .copied()
+            .or_else(|| LANGUAGES.get(ext))
+            .or_else(|| LANGUAGES.get(&lower))
+    }
+
+    /// Looks up a language by the exact name of a file, like `Makefile`.
+    pub fn from_file_name(&self, name: &str) -> Option<&'static Language<'static>> {
+        self.filenames
+            .get(name)
+            .copied()
+            .or_else(|| self.from_extension(FILENAMES.get(name)?))
+    }
+
+    /// Looks up a language by extension, by interpreter or by name, ignoring case, like
+    /// the languages of embedded code are named.
+    pub(crate) fn named(&self, name: &str) -> Option<&'static Language<'static>> {
+        self.from_extension(name)
+            .or_else(|| self.from_extension(INTERPRETERS.get(&name.to_lowercase())?))
+            .or_else(|| {
+                self.extensions
+                    .values()
+                    .copied()
+                    .chain(LANGUAGES.values())
+                    .find(|language| language.name.eq_ignore_ascii_case(name))
+            })
+    }
+
+    /// Detects the language of a file from a shebang in its first line or a vim or emacs
+    /// modeline, given its first [`MODELINE_LINES`] lines and its last ones, from the last
+    /// line backwards.
+    pub(crate) fn detect_lines<'a>(
+        &self,
+        head: impl IntoIterator<Item = &'a str>,
+        tail: impl IntoIterator<Item = &'a str>,
+    ) -> Option<&'static Language<'static>> {
+        let mut head = head.into_iter().peekable();
+        let shebang = head.peek().copied().and_then(from_shebang);
+        let ext = shebang.or_else(|| head.chain(tail).find_map(from_modeline))?;
+        self.from_extension(ext)
+    }
+
+    /// Detects the language of the file at `path` from its name: the exact file name, see
+    /// [`Languages::from_file_name`], or else the file extension, ignoring case, see
+    /// [`Languages::from_extension`]. Files with neither are detected from their contents,
+    /// see [`Languages::detect_lines`].
+    pub(crate) fn detect_name(&self, path: &Path) -> Option<&'static Language<'static>> {
+        let name = path.file_name()?.to_str()?;
+        self.from_file_name(name).or_else(|| {
+            let (_, ext) = name.rsplit_once('.')?;
+            self.from_extension(ext)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn name(language: Option<&'static Language<'static>>) -> Option<&'static str> {
+        language.map(|language| language.name)
+    }
+
+    #[test]
+    fn detects_names_and_extensions() {
+        let languages = Languages::default();
+        let detect = |path: &str| name(languages.detect_name(Path::new(path)));
+        assert_eq!(detect("src/main.rs"), Some("Rust"));
+        assert_eq!(detect("MAIN.RS"), Some("Rust"));
+        assert_eq!(detect("Makefile"), Some("make file"));
+        assert_eq!(detect("makefile"), Some("make file"));
+        assert_eq!(detect("GNUmakefile"), Some("make file"));
+        assert_eq!(detect("rules.mk"), Some("make file"));
+        assert_eq!(detect("README"), None);
+    }
+
+    #[test]
+    fn detects_shebangs_and_modelines() {
+        let languages = Languages::default();
+        let detect = |head: &[&str], tail: &[&str]| {
+            name(languages.detect_lines(head.iter().copied(), tail.iter().copied()))
+        };
+        assert_eq!(detect(&["#!/usr/bin/env python3"], &[]), Some("Python"));
+        assert_eq!(detect(&["#!/bin/bash -e"], &[]), Some("bash script"));
+        assert_eq!(detect(&["# -*- mode: ruby -*-"], &[]), Some("Ruby"));
+        assert_eq!(
+            detect(&["x"], &["# vim: set ft=python:", "x"]),
+            Some("Python")
+        );
+        // Shebangs only count on the first line.
+        assert_eq!(detect(&["x", "#!/usr/bin/env python3"], &[]), None);
+    }
+
+    #[test]
+    fn added_languages_replace_built_in_ones() {
+        static RUST: Language = Language::new("Rust", &[CommentSyntax::LineStart("#")]);
+        let mut languages = Languages::default();
+        languages.add(&RUST, ["rsx".to_string()], []);
+        let rust = languages.from_extension("rs").unwrap();
+        assert!(matches!(rust.comments, [CommentSyntax::LineStart("#")]));
+        assert_eq!(name(languages.from_extension("rsx")), Some("Rust"));
+    }
+}
@@ -0,0 +1,986 @@
+//! Counts lines of code in a directory tree, grouped by language.
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, VecDeque},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Cursor, Seek},
+    iter,
+    ops::{AddAssign, SubAssign},
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use encoding_rs::Encoding;
+use ignore::{overrides::Override, DirEntry, WalkBuilder, WalkState};
+
+mod blame;
+mod config;
+mod diff;
+mod embed;
+mod encoding;
+mod git;
+mod history;
+mod language;
+mod scanner;
+mod skip;
+#[cfg(test)]
+mod test_util;
+
+pub use blame::{blame, Author, AuthorCount, BlameResult};
+pub use config::CONFIG_FILE;
+pub use diff::{diff, Delta, DiffResult, FileDelta};
+use embed::Classifier;
+use encoding::{Lines, Next, ReadError};
+pub use git::count_rev;
+pub use history::{history, Sample, Snapshot};
+use language::MODELINE_LINES;
+pub use language::{CommentSyntax, EmbedSyntax, Language, Languages, StringSyntax, LANGUAGES};
+use scanner::{LineKind, Scanner};
+pub use skip::{EntryError, SkipReason, Skipped};
+use skip::{LineLengths, GENERATED_LINES};
+
+const IGNORE_DIRS: &[&str] = &["target", "build"];
+
+/// Name of the tool specific ignore file, using the same syntax as `.gitignore`.
+pub const LCIGNORE: &str = ".lcignore";
+
+/// How files are found and which languages they're counted as.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Honor `.gitignore` files.
+    pub gitignore: bool,
+    /// Honor `.git/info/exclude`.
+    pub git_exclude: bool,
+    /// Honor the global excludes file from git's `core.excludesFile`.
+    pub git_global: bool,
+    /// Honor `.ignore` files.
+    pub dot_ignore: bool,
+    /// Honor `.lcignore` files.
+    pub lcignore: bool,
+    /// The number of threads to walk and count with, `0` picks one per core.
+    pub threads: usize,
+    pub languages: Languages,
+    /// Globs in the syntax of `.gitignore` that files have to match to be counted, with
+    /// negated globs excluding files and directories. Unlike overrides of ignore files
+    /// they can't bring back ignored files.
+    pub globs: Override,
+    /// Names of the only languages to count, compared case-insensitively. All languages
+    /// are counted if empty.
+    pub include_languages: Vec<String>,
+    /// Names of languages not to count, compared case-insensitively.
+    pub exclude_languages: Vec<String>,
+    /// Count generated files instead of skipping them.
+    pub generated: bool,
+    /// Count minified files instead of skipping them.
+    pub minified: bool,
+    /// The encoding of all files. If `None` it's detected from the BOM, or else files are
+    /// UTF-16 if they look like it, UTF-8 if they're valid UTF-8 and Windows-1252 if not.
+    pub encoding: Option<&'static Encoding>,
+}
+
+impl Options {
+    /// Whether files of the language called `name` are counted.
+    fn counts_language(&self, name: &str) -> bool {
+        let listed = |names: &[String]| names.iter().any(|n| n.eq_ignore_ascii_case(name));
+        (self.include_languages.is_empty() || listed(&self.include_languages))
+            && !listed(&self.exclude_languages)
+    }
+
+    /// Whether the file at `path` may be of a language that's counted, judging by its name
+    /// alone. Files of languages detected from their contents are checked once counted.
+    fn may_count(&self, path: &Path) -> bool {
+        self.languages
+            .detect_name(path)
+            .is_none_or(|language| self.counts_language(language.name))
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            gitignore: true,
+            git_exclude: true,
+            git_global: true,
+            dot_ignore: true,
+            lcignore: true,
+            threads: 0,
+            languages: Languages::default(),
+            globs: Override::empty(),
+            include_languages: Vec::new(),
+            exclude_languages: Vec::new(),
+            generated: false,
+            minified: false,
+            encoding: None,
+        }
+    }
+}
+
+/// Line counts broken down by kind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Lines containing code, including lines with trailing comments.
+    pub code: usize,
+    /// Lines containing only comments.
+    pub comments: usize,
+    /// Lines containing only documentation comments, or documentation along with other
+    /// comments.
+    pub docs: usize,
+    /// Lines containing only whitespace.
+    pub blanks: usize,
+    /// The number of branch keywords and operators in code, an estimate of cyclomatic
+    /// complexity. It doesn't add to the number of lines.
+    pub complexity: usize,
+}
+
+impl Stats {
+    /// The total number of lines.
+    pub fn lines(&self) -> usize {
+        self.code + self.comments + self.docs + self.blanks
+    }
+
+    fn add_line(&mut self, kind: LineKind) {
+        match kind {
+            LineKind::Code => self.code += 1,
+            LineKind::Comment => self.comments += 1,
+            LineKind::Doc => self.docs += 1,
+            LineKind::Blank => self.blanks += 1,
+        }
+    }
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.code += rhs.code;
+        self.comments += rhs.comments;
+        self.docs += rhs.docs;
+        self.blanks += rhs.blanks;
+        self.complexity += rhs.complexity;
+    }
+}
+
+impl SubAssign for Stats {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.code -= rhs.code;
+        self.comments -= rhs.comments;
+        self.docs -= rhs.docs;
+        self.blanks -= rhs.blanks;
+        self.complexity -= rhs.complexity;
+    }
+}
+
+/// The lines counted in a single file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileCount {
+    pub path: PathBuf,
+    /// The name of the file's language, or its extension if the language isn't known.
+    /// This is the key of the file's entry in [`CountResult::languages`].
+    pub language: String,
+    /// The lines of the whole file, including those embedded in other languages.
+    pub stats: Stats,
+    /// The lines of code embedded in other languages, like scripts in HTML, keyed by
+    /// language name.
+    pub embedded: BTreeMap<String, Stats>,
+}
+
+impl FileCount {
+    /// The lines of each language in the file, starting with the file's own language.
+    pub fn languages(&self) -> impl Iterator<Item = (&str, Stats)> {
+        let mut own = self.stats;
+        for stats in self.embedded.values() {
+            own -= *stats;
+        }
+        let embedded = self.embedded.iter();
+        iter::once((self.language.as_str(), own))
+            .chain(embedded.map(|(language, stats)| (language.as_str(), *stats)))
+    }
+}
+
+/// The lines counted in a directory tree.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CountResult {
+    /// Line counts keyed by language name, see [`FileCount::languages`].
+    pub languages: HashMap<String, Stats>,
+    /// Every counted file, sorted by path.
+    pub files: Vec<FileCount>,
+    /// Every file that wasn't counted, sorted by path.
+    pub skipped: Vec<Skipped>,
+    /// Every path that couldn't be walked or read, sorted by path.
+    pub errors: Vec<EntryError>,
+    /// The sum of all line counts.
+    pub total: Stats,
+}
+
+impl CountResult {
+    /// Adds a counted file to the result.
+    pub fn add(&mut self, file: FileCount) {
+        for (language, stats) in file.languages() {
+            *self.languages.entry(language.to_string()).or_default() += stats;
+        }
+        self.total += file.stats;
+        self.files.push(file);
+    }
+
+    /// The sum of the line counts of all files under `root`.
+    pub fn total_under(&self, root: &Path) -> Stats {
+        let mut total = Stats::default();
+        for file in self.files.iter().filter(|file| is_under(&file.path, root)) {
+            total += file.stats;
+        }
+        total
+    }
+
+    /// The line counts of `root` and every directory under it down to `depth` levels below
+    /// it, each including the files of its subdirectories.
+    pub fn directories(&self, root: &Path, depth: Option<usize>) -> BTreeMap<PathBuf, Stats> {
+        let mut dirs = BTreeMap::<_, Stats>::new();
+        for file in self.files.iter().filter(|file| is_under(&file.path, root)) {
+            let mut dir = root.to_path_buf();
+            *dirs.entry(dir.clone()).or_default() += file.stats;
+            let relative = normalized(&file.path)
+                .strip_prefix(normalized(root))
+                .unwrap();
+            let parents = relative.parent().into_iter().flat_map(Path::components);
+            for component in parents.take(depth.unwrap_or(usize::MAX)) {
+                dir.push(component);
+                *dirs.entry(dir.clone()).or_default() += file.stats;
+            }
+        }
+        dirs
+    }
+
+    /// Makes the paths of all files relative to `root`, leaving paths outside of it as they
+    /// are.
+    pub fn strip_prefix(&mut self, root: &Path) {
+        let paths = self.files.iter_mut().map(|file| &mut file.path);
+        for path in paths.chain(self.skipped.iter_mut().map(|skip| &mut skip.path)) {
+            if let Ok(relative) = path.strip_prefix(root) {
+                *path = relative.to_path_buf();
+            }
+        }
+    }
+
+    /// Line counts keyed by file extension instead of by language.
+    pub fn by_extension(&self) -> HashMap<String, Stats> {
+        let mut extensions = HashMap::<_, Stats>::new();
+        for file in &self.files {
+            *extensions.entry(extension(&file.path)).or_default() += file.stats;
+        }
+        extensions
+    }
+}
+
+/// `path` without a leading `./`, so that paths walked from overlapping roots like `.` and
+/// `src` compare alike.
+fn normalized(path: &Path) -> &Path {
+    path.strip_prefix(".").unwrap_or(path)
+}
+
+/// Orders paths by their normalized form, then by the path itself.
+fn path_order(a: &Path, b: &Path) -> Ordering {
+    (normalized(a), a).cmp(&(normalized(b), b))
+}
+
+fn is_under(path: &Path, root: &Path) -> bool {
+    normalized(path).starts_with(normalized(root))
+}
+
+/// The extension of `path`, or its whole file name if it doesn't have one.
+fn extension(path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    name.split('.').next_back().unwrap().to_string()
+}
+
+/// Counts the code, comment, documentation and blank lines of `src`.
+///
+/// Without a `language` every non-blank line is counted as code.
+pub fn count_str(src: &str, language: Option<&Language>) -> Stats {
+    let mut stats = Stats::default();
+    let mut scanner = language.map(Scanner::new);
+    for line in src.lines() {
+        stats.add_line(classify(&mut scanner, line));
+    }
+    stats.complexity = scanner.map_or(0, |scanner| scanner.branches);
+    stats
+}
+
+/// The kind of `line`, classified by `scanner` if the language is known.
+fn classify(scanner: &mut Option<Scanner>, line: &str) -> LineKind {
+    match scanner {
+        Some(scanner) => scanner.classify(line),
+        None if line.trim().is_empty() => LineKind::Blank,
+        None => LineKind::Code,
+    }
+}
+
+/// The name files at `path` are grouped under, that of their `language` if it's known.
+fn language_name(path: &Path, language: Option<&Language>) -> String {
+    language.map_or_else(|| extension(path), |lang| lang.name.to_string())
+}
+
+/// How many bytes at the end of a file are searched for a modeline before reading it all.
+const MODELINE_TAIL: u64 = 4096;
+
+/// Detects the language of a file from its shebang or a vim or emacs modeline in its first
+/// and last lines, read from `lines`. The file is only read to its end if its last lines
+/// don't fit in its last [`MODELINE_TAIL`] bytes and those have a modeline.
+fn detect_content(
+    lines: &mut Lines<impl BufRead + Seek>,
+    languages: &Languages,
+) -> Result<Option<&'static Language<'static>>, ReadError> {
+    let (mut head, mut tail) = (Vec::new(), VecDeque::new());
+    let mut whole = true;
+    if let Some(end) = lines.tail(MODELINE_TAIL)? {
+        let modeline = languages.detect_lines([], end.lines().rev());
+        // The first line of the end may have started before it.
+        let end: Vec<_> = end.lines().skip(1).collect();
+        if end.len() >= MODELINE_LINES || modeline.is_none() {
+            let skip = end.len().saturating_sub(MODELINE_LINES);
+            tail.extend(end[skip..].iter().map(|line| line.to_string()));
+            whole = false;
+        }
+    }
+    lines.rewind()?;
+
+    let mut line = String::new();
+    let mut partial = false;
+    while whole || head.len() < MODELINE_LINES {
+        let next = lines.next(&mut line)?;
+        // Only the first part of a line too long to read at once is searched.
+        let continued = std::mem::replace(&mut partial, matches!(next, Next::Part));
+        match next {
+            Next::Line | Next::Part if continued => continue,
+            Next::Line | Next::Part => {}
+            Next::End => break,
+            Next::Restart => {
+                head.clear();
+                if whole {
+                    tail.clear();
+                }
+                continue;
+            }
+        }
+        if head.len() < MODELINE_LINES {
+            head.push(line.clone());
+        }
+        if whole {
+            if tail.len() == MODELINE_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line.clone());
+        }
+    }
+    lines.rewind()?;
+    Ok(languages.detect_lines(
+        head.iter().map(String::as_str),
+        tail.iter().rev().map(String::as_str),
+    ))
+}
+
+/// What [`classify_reader`] passes on as it reads a file.
+enum Event {
+    /// The next line, along with the language of the code embedded in it, if it's embedded
+    /// code.
+    Line(Option<&'static Language<'static>>, LineKind),
+    /// The lines start over, see [`Next::Restart`].
+    Restart,
+}
+
+/// The language of a file classified by [`classify_reader`] and the branches found in it.
+struct Classified {
+    language: Option<&'static Language<'static>>,
+    branches: usize,
+    /// The branches found in each language embedded in the file.
+    embedded_branches: Vec<(&'static Language<'static>, usize)>,
+}
+
+/// Classifies the lines of the file at `path` as they're read from `reader`, passing each
+/// to `on_event`, unless the file is skipped as binary, generated or minified according to
+/// `options`. Memory use doesn't grow with the size of the file.
+fn classify_reader(
+    path: &Path,
+    reader: impl BufRead + Seek,
+    options: &Options,
+    mut on_event: impl FnMut(Event),
+) -> Result<Classified, ReadError> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if !options.minified && skip::is_minified_name(&name) {
+        return Err(SkipReason::Minified.into());
+    }
+    let mut lines = Lines::new(reader, options.encoding)?;
+    let language = match options.languages.detect_name(path) {
+        Some(language) => Some(language),
+        None => detect_content(&mut lines, &options.languages)?,
+    };
+
+    let mut lengths = LineLengths::default();
+    let mut classifier = Classifier::new(language, &options.languages);
+    // Whether the current line has a generated marker so far.
+    let mut marked = false;
+    let mut line = String::new();
+    // The length of the parts of the current line read so far.
+    let mut len = 0;
+    loop {
+        let next = lines.next(&mut line)?;
+        match next {
+            Next::Line | Next::Part => {}
+            Next::End => break,
+            Next::Restart => {
+                lengths = LineLengths::default();
+                classifier = Classifier::new(language, &options.languages);
+                marked = false;
+                len = 0;
+                on_event(Event::Restart);
+                continue;
+            }
+        }
+        marked |= !options.generated
+            && lengths.lines() < GENERATED_LINES
+            && skip::is_generated_line(&line);
+        len += line.len();
+        classifier.scan(&line);
+        if let Next::Line = next {
+            lengths.add(std::mem::take(&mut len));
+            let (embedded_language, kind) = classifier.end_line();
+            // Markers in code, like in the strings of a generator, don't count.
+            if std::mem::take(&mut marked) && matches!(kind, LineKind::Comment | LineKind::Doc) {
+                return Err(SkipReason::Generated.into());
+            }
+            on_event(Event::Line(embedded_language, kind));
+        }
+    }
+    let minifiable = language.is_some_and(|language| language.minifiable);
+    if !options.minified && minifiable && lengths.are_minified() {
+        return Err(SkipReason::Minified.into());
+    }
+    let (branches, embedded_branches) = classifier.finish();
+    Ok(Classified {
+        language,
+        branches,
+        embedded_branches,
+    })
+}
+
+/// Counts the lines of the file at `path` as they're read from `reader`, see
+/// [`count_file`].
+fn count_reader(
+    path: &Path,
+    reader: impl BufRead + Seek,
+    options: &Options,
+) -> Result<FileCount, ReadError> {
+    let mut stats = Stats::default();
+    let mut embedded = HashMap::<_, Stats>::new();
+    let classified = classify_reader(path, reader, options, |event| match event {
+        Event::Line(embedded_language, kind) => {
+            stats.add_line(kind);
+            if let Some(embedded_language) = embedded_language {
+                embedded
+                    .entry(embedded_language.name)
+                    .or_default()
+                    .add_line(kind);
+            }
+        }
+        Event::Restart => {
+            stats = Stats::default();
+            embedded.clear();
+        }
+    })?;
+    stats.complexity = classified.branches;
+    for (embedded_language, branches) in classified.embedded_branches {
+        stats.complexity += branches;
+        embedded
+            .entry(embedded_language.name)
+            .or_default()
+            .complexity += branches;
+    }
+
+    Ok(FileCount {
+        path: path.to_path_buf(),
+        language: language_name(path, classified.language),
+        stats,
+        embedded: embedded
+            .into_iter()
+            .map(|(name, stats)| (name.to_string(), stats))
+            .collect(),
+    })
+}
+
+/// The outcome of reading a file from memory, which can only fail by skipping the file.
+fn from_memory<T>(res: Result<T, ReadError>) -> Result<T, SkipReason> {
+    match res {
+        Ok(value) => Ok(value),
+        Err(ReadError::Skip(reason)) => Err(reason),
+        Err(ReadError::Io(err)) => unreachable!("reading from memory failed: {err}"),
+    }
+}
+
+/// Counts the lines of `bytes`, the contents of the file at `path`, see [`count_file`].
+fn count_bytes(path: &Path, bytes: &[u8], options: &Options) -> Result<FileCount, SkipReason> {
+    from_memory(count_reader(path, Cursor::new(bytes), options))
+}
+
+/// Counts the lines of the file at `path`, unless it's skipped as binary, generated or
+/// minified according to `options`. The file is read a line at a time.
+pub fn count_file(path: &Path, options: &Options) -> io::Result<Result<FileCount, SkipReason>> {
+    match count_reader(path, BufReader::new(File::open(path)?), options) {
+        Ok(file) => Ok(Ok(file)),
+        Err(ReadError::Skip(reason)) => Ok(Err(reason)),
+        Err(ReadError::Io(err)) => Err(err),
+    }
+}
+
+/// Whether the directory called `name` is skipped, as hidden or build directories are.
+fn skip_dir(name: &str) -> bool {
+    name.starts_with('.') || IGNORE_DIRS.contains(&name)
+}
+
+/// What became of an entry of a walk.
+enum Walked {
+    Counted(FileCount),
+    Skipped(Skipped),
+    Failed(EntryError),
+}
+
+/// Combines walked entries into a result that doesn't depend on the order they were
+/// walked in.
+fn collect(entries: Vec<Walked>) -> CountResult {
+    let (mut files, mut skipped, mut errors) = (Vec::new(), Vec::new(), Vec::new());
+    for entry in entries {
+        match entry {
+            Walked::Counted(file) => files.push(file),
+            Walked::Skipped(skip) => skipped.push(skip),
+            Walked::Failed(err) => errors.push(err),
+        }
+    }
+    // Overlapping paths would otherwise count the same file twice.
+    files.sort_by(|a, b| path_order(&a.path, &b.path));
+    files.dedup_by(|a, b| normalized(&a.path) == normalized(&b.path));
+    skipped.sort_by(|a, b| path_order(&a.path, &b.path));
+    skipped.dedup_by(|a, b| normalized(&a.path) == normalized(&b.path));
+    errors.sort_by(|a, b| {
+        let key = |err: &EntryError| err.path.as_deref().map(normalized).map(Path::to_path_buf);
+        (key(a), &a.message, &a.path).cmp(&(key(b), &b.message, &b.path))
+    });
+    errors.dedup_by(|a, b| {
+        a.message == b.message
+            && a.path.as_deref().map(normalized) == b.path.as_deref().map(normalized)
+    });
+
+    let mut res = CountResult {
+        skipped,
+        errors,
+        ..Default::default()
+    };
+    for file in files {
+        res.add(file);
+    }
+    res
+}
+
+/// The entries failing because of an error of the walk, one per path it happened at.
+fn walk_failures(err: ignore::Error) -> Vec<Walked> {
+    let mut errors = Vec::new();
+    skip::walk_errors(err, None, &mut errors);
+    errors.into_iter().map(Walked::Failed).collect()
+}
+
+/// What became of a walk entry, including errors in the ignore files of its directory.
+fn walk_entry(entry: Result<DirEntry, ignore::Error>, options: &Options) -> Vec<Walked> {
+    let entry = match entry {
+        Ok(entry) => entry,
+        Err(err) => return walk_failures(err),
+    };
+    // Ignore files that couldn't be read or parsed only come with the entry of their
+    // directory.
+    let mut walked = entry
+        .error()
+        .cloned()
+        .map(walk_failures)
+        .unwrap_or_default();
+    walked.extend(count_entry(&entry, options));
+    walked
+}
+
+/// Counts the file of a walk entry, if it's a file of a language that's counted.
+fn count_entry(entry: &DirEntry, options: &Options) -> Vec<Walked> {
+    let path = entry.path();
+    let failed = |err: io::Error| {
+        vec![Walked::Failed(EntryError {
+            path: Some(path.to_path_buf()),
+            message: err.to_string(),
+        })]
+    };
+    // Links aren't followed, but broken ones are reported.
+    if entry.file_type().is_some_and(|t| t.is_symlink()) {
+        return fs::metadata(path).err().map(failed).unwrap_or_default();
+    }
+    if !entry.file_type().is_some_and(|t| t.is_file()) || !options.may_count(path) {
+        return Vec::new();
+    }
+    match count_file(path, options) {
+        Ok(Ok(file)) if options.counts_language(&file.language) => vec![Walked::Counted(file)],
+        Ok(Ok(_)) => Vec::new(),
+        Ok(Err(reason)) => vec![Walked::Skipped(Skipped {
+            path: path.to_path_buf(),
+            reason,
+        })],
+        Err(err) => failed(err),
+    }
+}
+
+/// Counts the lines of every file under `path`, see [`count_paths`].
+pub fn count_path(path: &Path, options: &Options) -> CountResult {
+    count_paths(&[path], options)
+}
+
+/// Counts the lines of every file under each of `paths` into one result. Paths can be
+/// directories or single files.
+///
+/// Hidden and build directories are skipped, as well as anything ignored by the ignore
+/// files enabled in `options`, with `.gitignore` files honored outside of git checkouts
+/// too, like [`count_rev`] does. Symbolic links aren't followed. Files are walked and counted
+/// in parallel, the result doesn't depend on the thread count. Paths that can't be walked
+/// or read are listed in [`CountResult::errors`].
+pub fn count_paths(paths: &[impl AsRef<Path>], options: &Options) -> CountResult {
+    let Some((first, rest)) = paths.split_first() else {
+        return CountResult::default();
+    };
+    let mut walker = WalkBuilder::new(first);
+    for path in rest {
+        walker.add(path);
+    }
+    let globs = options.globs.clone();
+    walker
+        .hidden(false)
+        .git_ignore(options.gitignore)
+        .require_git(false)
+        .git_exclude(options.git_exclude)
+        .git_global(options.git_global)
+        .ignore(options.dot_ignore)
+        .threads(options.threads)
+        .filter_entry(move |e| {
+            let is_dir = e.file_type().is_some_and(|t| t.is_dir());
+            e.depth() == 0
+                || !(is_dir && skip_dir(&e.file_name().to_string_lossy())
+                    || globs.matched(e.path(), is_dir).is_ignore())
+        });
+    if options.lcignore {
+        walker.add_custom_ignore_filename(LCIGNORE);
+    }
+
+    let (tx, rx) = mpsc::channel();
+    walker.build_parallel().run(|| {
+        let tx = tx.clone();
+        Box::new(move |entry| {
+            for walked in walk_entry(entry, options) {
+                tx.send(walked).unwrap();
+            }
+            WalkState::Continue
+        })
+    });
+    drop(tx);
+
+    collect(rx.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{globs, TempDir};
+
+    #[test]
+    fn overlapping_roots_are_counted_once() {
+        let file = |path: &str| {
+            Walked::Counted(FileCount {
+                path: PathBuf::from(path),
+                language: "Rust".to_string(),
+                stats: count_str("fn a() {}", Some(&LANGUAGES["rs"])),
+                embedded: BTreeMap::new(),
+            })
+        };
+        let res = collect(vec![file("./src/a.rs"), file("src/a.rs"), file("./b.rs")]);
+        assert_eq!(res.files.len(), 2);
+        assert_eq!(res.total.code, 2);
+        assert_eq!(res.total_under(Path::new("src")).code, 1);
+        assert_eq!(res.total_under(Path::new(".")).code, 2);
+    }
+
+    #[test]
+    fn results_dont_depend_on_the_thread_count() {
+        let dir = TempDir::new("threads");
+        let mut files = Vec::new();
+        for i in 0..20 {
+            files.push((format!("src/m{i}/a.rs"), "// a\nfn a() {}\n".repeat(i + 1)));
+            files.push((format!("src/m{i}/b.py"), format!("b = {i}\n")));
+        }
+        files.push(("x.min.js".to_string(), "var x;".to_string()));
+        let files: Vec<_> = files
+            .iter()
+            .map(|(path, src)| (path.as_str(), src.as_str()))
+            .collect();
+        dir.write(&files);
+
+        // The root is also reached through a path overlapping it.
+        let roots = [dir.path.clone(), dir.path.clone(), dir.path.join("src/m3")];
+        let count = |threads| {
+            let options = Options {
+                threads,
+                ..Options::default()
+            };
+            count_paths(&roots, &options)
+        };
+        let res = count(1);
+        assert_eq!(res.files.len(), 40);
+        assert_eq!(res.skipped.len(), 1);
+        assert_eq!(res, count(8));
+    }
+
+    #[test]
+    fn directories_roll_up_down_to_a_depth() {
+        let mut res = CountResult::default();
+        for (path, src) in [("src/a.rs", "a"), ("src/x/y/b.rs", "b\nb"), ("c.rs", "c")] {
+            res.add(FileCount {
+                path: PathBuf::from(path),
+                language: "Rust".to_string(),
+                stats: count_str(src, Some(&LANGUAGES["rs"])),
+                embedded: BTreeMap::new(),
+            });
+        }
+        let code = |depth| {
+            res.directories(Path::new("."), depth)
+                .into_iter()
+                .map(|(dir, stats)| (dir.to_str().unwrap().to_string(), stats.code))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(code(Some(0)), [(".".to_string(), 4)]);
+        assert_eq!(
+            code(Some(1)),
+            [(".".to_string(), 4), ("./src".to_string(), 3)]
+        );
+        assert_eq!(
+            code(None),
+            [
+                (".".to_string(), 4),
+                ("./src".to_string(), 3),
+                ("./src/x".to_string(), 2),
+                ("./src/x/y".to_string(), 2),
+            ]
+        );
+        // Only directories under the root are listed.
+        assert_eq!(res.directories(Path::new("src/x"), None).len(), 2);
+    }
+
+    #[test]
+    fn embedded_code_counts_under_its_own_language() {
+        let src = "<p>\n<script>\nif (a) {}\n// b\n</script>\n```\n";
+        let file = count_bytes(Path::new("a.html"), src.as_bytes(), &Options::default()).unwrap();
+        assert_eq!((file.stats.code, file.stats.comments), (5, 1));
+        assert_eq!(file.stats.complexity, 1);
+        let mut res = CountResult::default();
+        res.add(file);
+        let (mut html, js) = (res.languages["html"], res.languages["javascript"]);
+        assert_eq!((html.code, html.comments, html.complexity), (4, 0, 0));
+        assert_eq!((js.code, js.comments, js.complexity), (1, 1, 1));
+        html += js;
+        assert_eq!(res.total, html);
+    }
+
+    #[test]
+    fn honors_each_layer_of_ignore_files() {
+        let dir = TempDir::new("ignore-layers");
+        dir.write(&[
+            (".git/info/exclude", "b.rs\n"),
+            (".gitignore", "a.rs\n"),
+            (".ignore", "c.rs\n"),
+            (LCIGNORE, "d.rs\n"),
+            ("a.rs", "fn a() {}\n"),
+            ("b.rs", "fn b() {}\n"),
+            ("c.rs", "fn c() {}\n"),
+            ("d.rs", "fn d() {}\n"),
+            ("e.rs", "fn e() {}\n"),
+        ]);
+        // The user's global excludes aren't part of the test.
+        let options = Options {
+            git_global: false,
+            include_languages: vec!["Rust".to_string()],
+            ..Options::default()
+        };
+        assert_eq!(counted(&dir, &options), ["e.rs"]);
+
+        let without = |layer: fn(&mut Options)| {
+            let mut options = options.clone();
+            layer(&mut options);
+            counted(&dir, &options)
+        };
+        assert_eq!(without(|o| o.gitignore = false), ["a.rs", "e.rs"]);
+        assert_eq!(without(|o| o.git_exclude = false), ["b.rs", "e.rs"]);
+        assert_eq!(without(|o| o.dot_ignore = false), ["c.rs", "e.rs"]);
+        assert_eq!(without(|o| o.lcignore = false), ["d.rs", "e.rs"]);
+    }
+
+    #[test]
+    fn honors_gitignore_outside_of_git_checkouts() {
+        let dir = TempDir::new("gitignore-without-git");
+        dir.write(&[(".gitignore", "a.rs\n"), ("a.rs", "a"), ("b.rs", "b")]);
+        let options = Options {
+            git_global: false,
+            include_languages: vec!["Rust".to_string()],
+            ..Options::default()
+        };
+        assert_eq!(counted(&dir, &options), ["b.rs"]);
+    }
+
+    #[test]
+    fn skips_binary_generated_and_minified_files() {
+        let count = |name: &str, src: &str, options: &Options| {
+            count_bytes(Path::new(name), src.as_bytes(), options).map(|file| file.stats.code)
+        };
+        let options = Options::default();
+        assert_eq!(
+            count("a.rs", "fn a() {}\0", &options),
+            Err(SkipReason::Binary)
+        );
+        let generated = "// @generated\nfn a() {}\n";
+        assert_eq!(
+            count("a.rs", generated, &options),
+            Err(SkipReason::Generated)
+        );
+        let generator =
+            "package gen\n\nconst Header = \"// Code generated by gen. DO NOT EDIT.\"\n";
+        assert_eq!(count("gen.go", generator, &options), Ok(2));
+        // Markers past the first lines don't count.
+        let late = format!("{}// @generated\n", "fn a() {}\n".repeat(GENERATED_LINES));
+        assert_eq!(count("a.rs", &late, &options), Ok(GENERATED_LINES));
+        let minified = format!("var a = {};\n", "1 + ".repeat(500));
+        assert_eq!(
+            count("a.js", &minified, &options),
+            Err(SkipReason::Minified)
+        );
+        // Long lines only mark languages that get minified, unlike SQL dumps.
+        let dump = format!("INSERT INTO a VALUES {};\n", "(1), ".repeat(500));
+        assert_eq!(count("dump.sql", &dump, &options), Ok(1));
+        assert_eq!(
+            count("a.min.js", "var a;", &options),
+            Err(SkipReason::Minified)
+        );
+
+        let options = Options {
+            generated: true,
+            minified: true,
+            ..Options::default()
+        };
+        assert_eq!(count("a.rs", generated, &options), Ok(1));
+        assert_eq!(count("a.js", &minified, &options), Ok(1));
+    }
+
+    #[test]
+    fn detects_modelines_at_the_end_of_long_files() {
+        let count = |src: &str| {
+            count_bytes(Path::new("script"), src.as_bytes(), &Options::default())
+                .unwrap()
+                .language
+        };
+        let body = "x = 1\n".repeat(2000);
+        assert_eq!(count(&format!("{body}# vim: set ft=python:\n")), "Python");
+        // Modelines are only searched for in the first and last lines.
+        let middle = format!("{body}# vim: set ft=python:\n{body}");
+        assert_eq!(count(&middle), "script");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn collects_paths_that_cant_be_read() {
+        let dir = TempDir::new("errors");
+        dir.write(&[("a.rs", "fn a() {}\n")]);
+        std::os::unix::fs::symlink(dir.path.join("gone.rs"), dir.path.join("b.rs")).unwrap();
+
+        let missing = dir.path.join("missing");
+        let res = count_paths(&[&dir.path, &missing], &Options::default());
+        assert_eq!(res.files.len(), 1);
+        let paths: Vec<_> = res.errors.iter().map(|err| err.path.clone()).collect();
+        assert_eq!(paths, [Some(dir.path.join("b.rs")), Some(missing)]);
+    }
+
+    #[test]
+    fn collects_errors_in_ignore_files() {
+        let dir = TempDir::new("bad-ignore");
+        dir.write(&[
+            ("a.rs", "fn a() {}\n"),
+            ("sub/.ignore", "{a\nb.rs\n"),
+            ("sub/b.rs", "fn b() {}\n"),
+            ("sub/c.rs", "fn c() {}\n"),
+        ]);
+
+        let mut res = count_path(&dir.path, &Options::default());
+        res.strip_prefix(&dir.path);
+        // The valid rules of the file still apply.
+        let paths: Vec<_> = res
+            .files
+            .iter()
+            .map(|file| file.path.to_str().unwrap())
+            .collect();
+        assert_eq!(paths, ["a.rs", "sub/.ignore", "sub/c.rs"]);
+        assert_eq!(res.errors.len(), 1);
+        assert_eq!(res.errors[0].path, Some(dir.path.join("sub/.ignore")));
+        assert!(res.errors[0].message.contains("{a"));
+    }
+
+    fn counted(dir: &TempDir, options: &Options) -> Vec<String> {
+        let mut res = count_path(&dir.path, options);
+        res.strip_prefix(&dir.path);
+        res.files
+            .iter()
+            .map(|file| file.path.to_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn filters_files_by_glob() {
+        let dir = TempDir::new("globs");
+        dir.write(&[
+            ("a.rs", "fn a() {}\n"),
+            ("b.py", "b = 1\n"),
+            ("src/c.rs", "fn c() {}\n"),
+            ("tests/d.rs", "fn d() {}\n"),
+            // Read only if the excluded directory is walked.
+            ("vendor/.ignore", "{a\n"),
+            ("vendor/e.rs", "fn e() {}\n"),
+        ]);
+
+        let options = Options {
+            globs: globs(&dir.path, &["**/*.rs"], &["tests/", "vendor/"]),
+            ..Options::default()
+        };
+        assert_eq!(counted(&dir, &options), ["a.rs", "src/c.rs"]);
+        let options = Options {
+            globs: globs(&dir.path, &[], &["vendor/"]),
+            ..Options::default()
+        };
+        assert_eq!(
+            counted(&dir, &options),
+            ["a.rs", "b.py", "src/c.rs", "tests/d.rs"]
+        );
+        assert!(count_path(&dir.path, &options).errors.is_empty());
+    }
+
+    #[test]
+    fn filters_files_by_language() {
+        let dir = TempDir::new("langs");
+        dir.write(&[
+            ("a.rs", "fn a() {}\n"),
+            ("b.py", "b = 1\n"),
+            ("c.xyz", "c\n"),
+        ]);
+
+        let options = Options {
+            include_languages: vec!["rust".to_string(), "XYZ".to_string()],
+            ..Options::default()
+        };
+        assert_eq!(counted(&dir, &options), ["a.rs", "c.xyz"]);
+        let options = Options {
+            exclude_languages: vec!["PYTHON".to_string(), "xyz".to_string()],
+            ..Options::default()
+        };
+        assert_eq!(counted(&dir, &options), ["a.rs"]);
+    }
+}
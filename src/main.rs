@@ -1,219 +1,473 @@
 use std::{
-    collections::HashMap,
-    fs,
+    env, io,
     path::{Path, PathBuf},
+    process::ExitCode,
 };
 
-use clap::Parser;
-use phf::phf_map;
-use walkdir::WalkDir;
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use encoding_rs::Encoding;
+use ignore::overrides::OverrideBuilder;
+use lc::{CountResult, Languages, Options, Sample};
+
+mod output;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// Directory to count the lines of
-    #[arg(short, long, default_value_t = String::from("."))]
-    directory: String,
-
-    /// If this should take comments into account
-    #[arg(long = "comments", default_value_t = false)]
-    count_comments: bool,
-    // If this should take empty lines into account
-    #[arg(long = "empty", default_value_t = false)]
-    count_empty: bool,
-}
-
-enum CommentSyntax<'a> {
-    LineStart(&'a str),
-    Range(&'a str, &'a str),
-}
-
-const C_STYLE: &[CommentSyntax] = &[
-    CommentSyntax::LineStart("//"),
-    CommentSyntax::Range("/*", "*/"),
-];
-const HASH: &[CommentSyntax] = &[CommentSyntax::LineStart("#")];
-const MATLAB: &[CommentSyntax] = &[
-    CommentSyntax::LineStart("%"),
-    CommentSyntax::Range("%{", "}%"),
-];
-const SCHEME: &[CommentSyntax] = &[
-    CommentSyntax::LineStart(";"),
-    CommentSyntax::Range("#|", "|#"),
-];
-
-struct Language<'a> {
-    name: &'a str,
-    comments: &'a [CommentSyntax<'a>],
-}
-
-impl<'a> Language<'a> {
-    const fn new(name: &'a str, comments: &'a [CommentSyntax<'a>]) -> Self {
-        Self { name, comments }
-    }
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    count: CountArgs,
+
+    #[command(flatten)]
+    walk: WalkArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compare the line counts of two paths or git revisions
+    Diff(DiffArgs),
+    /// Count the lines at each commit of a branch, from oldest to newest
+    History(HistoryArgs),
+    /// Attribute the lines of a revision to the authors who last changed them
+    Blame(BlameArgs),
 }
 
-const IGNORE_DIRS: &[&str] = &["target", "build"];
+#[derive(ClapArgs, Debug)]
+struct CountArgs {
+    /// Directories and files to count the lines of
+    #[arg(default_value = ".")]
+    paths: Vec<PathBuf>,
 
-static LANGUAGES: phf::Map<&'static str, Language<'static>> = phf_map! {
-    "rs" => Language::new("Rust", C_STYLE),
-    "go" => Language::new("Go", C_STYLE),
-    "h" => Language::new("C", C_STYLE),
-    "c" => Language::new("C", C_STYLE),
-    "hpp" => Language::new("C++", C_STYLE),
-    "cpp" => Language::new("C++", C_STYLE),
-    "cs" => Language::new("C#", C_STYLE),
-    "java" => Language::new("Java", C_STYLE),
-    "js" => Language::new("javascript", C_STYLE),
-    "carbon" => Language::new("Carbon", C_STYLE),
-    "swift" => Language::new("Swift", C_STYLE),
-    "dart" => Language::new("Dart", C_STYLE),
-    "sc" => Language::new("Scala", C_STYLE),
-    "kt" => Language::new("Kotlin", C_STYLE),
-    "hla" => Language::new("HLA", C_STYLE),
-    "lua" => Language::new("Lua", C_STYLE),
-    "rhai" => Language::new("Rhai", C_STYLE),
+    /// Directory to count the lines of, kept for compatibility with older versions
+    #[arg(short, long, hide = true, conflicts_with = "paths")]
+    directory: Vec<PathBuf>,
 
-    "ts" => Language::new("Scala", &[CommentSyntax::Range("/**", "*/")]),
+    /// Comment lines are always counted, kept for compatibility with older versions
+    #[arg(long, hide = true)]
+    comments: bool,
 
+    /// Blank lines are always counted, kept for compatibility with older versions
+    #[arg(long, hide = true)]
+    empty: bool,
 
-    "wgsl" => Language::new("wglsl", C_STYLE),
-    "glsl" => Language::new("glsl", C_STYLE),
-    "hlsl" => Language::new("hlsl", C_STYLE),
+    /// List every counted file instead of only the totals per language
+    #[arg(long)]
+    files: bool,
 
+    /// Group the totals by file extension instead of by language
+    #[arg(long)]
+    by_extension: bool,
 
-    "php" => Language::new("Swift", &[CommentSyntax::LineStart("//"), CommentSyntax::LineStart("#"), CommentSyntax::Range("/*", "*/")]),
-    "hs" => Language::new("Haskell", &[CommentSyntax::LineStart("--"), CommentSyntax::Range("{-", "-}")]),
-    "rb" => Language::new("Ruby", &[CommentSyntax::LineStart("#"), CommentSyntax::Range("=begin", "=end")]),
-    "asm" => Language::new("Assembly", &[CommentSyntax::LineStart(";")]),
-    "tao" => Language::new("Tao", &[CommentSyntax::LineStart("##")]),
+    /// Also report the total of each path given on the command line
+    #[arg(long)]
+    per_root: bool,
 
-    "html" => Language::new("html", &[CommentSyntax::Range("<!--", "-->")]),
-    "css" => Language::new("css", &[CommentSyntax::Range("/*", "*/")]),
-    "zig" => Language::new("Zig", &[CommentSyntax::LineStart("//")]),
+    /// Column to sort by; counts sort from largest to smallest
+    #[arg(short, long, value_enum)]
+    sort: Option<output::Column>,
 
-    "py" => Language::new("Python", HASH),
-    "r" => Language::new("R", HASH),
-    "pl" => Language::new("Perl", HASH),
-    "emojic" => Language::new("emojicode", HASH),
+    /// Report an estimate of cyclomatic complexity next to the code lines, counting the
+    /// branch keywords and operators in code
+    #[arg(long)]
+    complexity: bool,
 
-    "toml" => Language::new("TOML", HASH),
-    "gitignore" => Language::new("git ignore", HASH),
-    "makefile" => Language::new("make file", HASH),
-    "bash" => Language::new("bash script", HASH),
+    /// Show the totals of each directory, including its subdirectories, instead of those
+    /// of each language
+    #[arg(long, conflicts_with_all = ["files", "by_extension", "per_root", "sort"])]
+    tree: bool,
 
-    "bat" => Language::new("batch script", &[CommentSyntax::LineStart("Rem"), CommentSyntax::LineStart("::")]),
+    /// How many levels of directories to show with --tree
+    #[arg(long, requires = "tree")]
+    depth: Option<usize>,
 
-    "m" => Language::new("Matlab", MATLAB),
-    "mat" => Language::new("Matlab", MATLAB),
+    /// Count the files of this git revision instead of the working tree, without checking
+    /// it out
+    #[arg(long, value_name = "COMMIT-ISH")]
+    rev: Option<String>,
 
-    "ss" => Language::new("Scheme", SCHEME),
-    "sls" => Language::new("Scheme", SCHEME),
-    "scm" => Language::new("Scheme", SCHEME),
-};
+    #[command(flatten)]
+    read: ReadArgs,
+}
+
+#[derive(ClapArgs, Debug)]
+struct DiffArgs {
+    /// Path or git revision to compare from
+    old: String,
+
+    /// Path or git revision to compare to. Sides that exist on disk are counted as paths,
+    /// others as revisions
+    new: String,
+
+    /// Directory to count in the repository when comparing revisions
+    #[arg(long, default_value = ".")]
+    path: PathBuf,
+
+    /// List every changed file instead of only the changes per language
+    #[arg(long)]
+    files: bool,
+
+    #[command(flatten)]
+    read: ReadArgs,
+}
+
+#[derive(ClapArgs, Debug)]
+struct HistoryArgs {
+    /// Branch or other revision whose history to count
+    #[arg(long, value_name = "COMMIT-ISH", default_value = "HEAD")]
+    rev: String,
+
+    /// Which commits to count
+    #[arg(long, value_enum, default_value_t = Every::Commit)]
+    every: Every,
+
+    /// Directory to count in the repository
+    #[arg(long, default_value = ".")]
+    path: PathBuf,
+}
+
+#[derive(ClapArgs, Debug)]
+struct BlameArgs {
+    /// Revision whose lines to attribute
+    #[arg(long, value_name = "COMMIT-ISH", default_value = "HEAD")]
+    rev: String,
+
+    /// Directory to count in the repository
+    #[arg(long, default_value = ".")]
+    path: PathBuf,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Every {
+    /// Every commit
+    Commit,
+    /// The last commit of each day
+    Day,
+    /// The last commit of each week
+    Week,
+    /// Every tagged commit
+    Tag,
+}
+
+impl From<Every> for Sample {
+    fn from(every: Every) -> Self {
+        match every {
+            Every::Commit => Sample::Commit,
+            Every::Day => Sample::Day,
+            Every::Week => Sample::Week,
+            Every::Tag => Sample::Tag,
+        }
+    }
+}
+
+/// Options shared by all commands.
+#[derive(ClapArgs, Debug)]
+struct WalkArgs {
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t, global = true)]
+    format: output::Format,
+
+    /// Additional config files defining languages, on top of lc.toml in the user config
+    /// directory and lc.toml in the current directory. lc.toml files in the counted
+    /// directories aren't read unless they're the current directory or given here
+    #[arg(long, global = true)]
+    config: Vec<PathBuf>,
+
+    /// Only count files matching this glob, in .gitignore syntax; may be repeated
+    #[arg(long, value_name = "GLOB", global = true)]
+    include: Vec<String>,
+
+    /// Don't count files or directories matching this glob, in .gitignore syntax; may be
+    /// repeated
+    #[arg(long, value_name = "GLOB", global = true)]
+    exclude: Vec<String>,
+
+    /// Only count files of this language; may be repeated
+    #[arg(long, value_name = "LANGUAGE", global = true)]
+    lang: Vec<String>,
+
+    /// Don't count files of this language; may be repeated
+    #[arg(long, value_name = "LANGUAGE", global = true)]
+    exclude_lang: Vec<String>,
+
+    /// Count generated files, which are skipped by default
+    #[arg(long, global = true)]
+    generated: bool,
+
+    /// Count minified files, which are skipped by default
+    #[arg(long, global = true)]
+    minified: bool,
+
+    /// Read all files in this encoding, like latin1 or utf-16le, instead of detecting it
+    #[arg(long, value_name = "LABEL", value_parser = parse_encoding, global = true)]
+    encoding: Option<&'static Encoding>,
+
+    /// Don't respect any ignore files
+    #[arg(long, global = true)]
+    no_ignore: bool,
+    /// Don't respect .gitignore files
+    #[arg(long, global = true)]
+    no_ignore_vcs: bool,
+    /// Don't respect .git/info/exclude
+    #[arg(long, global = true)]
+    no_ignore_exclude: bool,
+    /// Don't respect the global git excludes file
+    #[arg(long, global = true)]
+    no_ignore_global: bool,
+    /// Don't respect .ignore files
+    #[arg(long, global = true)]
+    no_ignore_dot: bool,
+    /// Don't respect .lcignore files
+    #[arg(long, global = true)]
+    no_ignore_lc: bool,
+}
 
-#[derive(Default)]
-struct CountResult {
-    languages: HashMap<String, usize>,
-    total: usize,
-}
-
-fn count_dir(dir: &Path, count_empty: bool, count_comments: bool) -> CountResult {
-    let mut res = CountResult::default();
-    for entry in WalkDir::new(dir)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| {
-            e.file_type().is_file()
-                && !IGNORE_DIRS.iter().any(|d| {
-                    e.path()
-                        .ancestors()
-                        .find(|anc| {
-                            anc != &e.path()
-                                && (anc.to_str().map_or(false, |s| s.contains("/."))
-                                    || anc.ends_with(d))
-                        })
-                        .is_some()
-                })
+impl WalkArgs {
+    fn options(&self) -> io::Result<Options> {
+        let mut languages = Languages::from_config()?;
+        for path in &self.config {
+            languages.load(path)?;
+        }
+
+        // Globs are relative to the current directory, like the paths given.
+        let mut globs = OverrideBuilder::new(env::current_dir()?);
+        for glob in &self.include {
+            globs.add(glob).map_err(io::Error::other)?;
+        }
+        for glob in &self.exclude {
+            globs.add(&format!("!{glob}")).map_err(io::Error::other)?;
+        }
+
+        Ok(Options {
+            gitignore: !(self.no_ignore || self.no_ignore_vcs),
+            git_exclude: !(self.no_ignore || self.no_ignore_exclude),
+            git_global: !(self.no_ignore || self.no_ignore_global),
+            dot_ignore: !(self.no_ignore || self.no_ignore_dot),
+            lcignore: !(self.no_ignore || self.no_ignore_lc),
+            // Only commands that walk in parallel take a thread count, see `ReadArgs`.
+            threads: 0,
+            languages,
+            globs: globs.build().map_err(io::Error::other)?,
+            include_languages: self.lang.clone(),
+            exclude_languages: self.exclude_lang.clone(),
+            generated: self.generated,
+            minified: self.minified,
+            encoding: self.encoding,
         })
-    {
-        let Ok(src) = fs::read_to_string(entry.path()) else {
-            continue;
-        };
+    }
+}
 
-        let name = entry.file_name().to_string_lossy();
-        let lang = name.split('.').last().unwrap();
-
-        let mut lines = src.lines().filter_map(|line| {
-            let line = line.trim();
-            (count_empty || !line.is_empty()).then_some(line)
-        });
-
-        let lines = if let Some(language) = LANGUAGES.get(lang).filter(|_| !count_comments) {
-            let mut count = 0;
-            while let Some(line) = lines.next() {
-                let mut skip = false;
-                'comments: for sntx in language.comments {
-                    match sntx {
-                        CommentSyntax::LineStart(start) => {
-                            if line.starts_with(start) {
-                                skip = true;
-                                break;
-                            }
-                        }
-                        CommentSyntax::Range(start, end) => {
-                            if let Some(i) = line
-                                .find(start)
-                                .filter(|i| line.find(end).map_or(true, |j| j < *i))
-                            {
-                                if i > 0 {
-                                    count += 1;
-                                }
-                                while let Some(line) = lines.next() {
-                                    if let Some(i) = line.find(end) {
-                                        skip = i + end.len() == line.len();
-
-                                        break 'comments;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                if !skip {
-                    count += 1;
-                }
-            }
-            count
-        } else {
-            lines.count()
-        };
+/// Options of the commands that count files on disk, which blame and history don't.
+#[derive(ClapArgs, Debug)]
+struct ReadArgs {
+    /// Number of threads to use, defaults to one per core
+    #[arg(short = 'j', long, default_value_t = 0)]
+    threads: usize,
 
-        *res.languages.entry(lang.to_string()).or_insert(0) += lines;
-        res.total += lines;
+    /// Exit with an error if any path couldn't be walked or read
+    #[arg(long)]
+    strict: bool,
+}
+
+impl ReadArgs {
+    /// Fails in strict mode if any path couldn't be walked or read, listing those paths.
+    fn check_errors(&self, results: &[&CountResult]) -> io::Result<()> {
+        let errors: Vec<_> = results.iter().flat_map(|res| &res.errors).collect();
+        if !self.strict || errors.is_empty() {
+            return Ok(());
+        }
+        let paths = if errors.len() == 1 { "path" } else { "paths" };
+        let mut message = format!("{} {paths} couldn't be walked or read:", errors.len());
+        for err in errors {
+            match &err.path {
+                Some(path) => message += &format!("\n  {}: {}", path.display(), err.message),
+                None => message += &format!("\n  {}", err.message),
+            }
+        }
+        Err(io::Error::other(message))
     }
-    res
 }
 
-fn main() {
-    let args = Args::parse();
-    let res = count_dir(
-        &PathBuf::from(args.directory),
-        args.count_empty,
-        args.count_comments,
+fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| format!("unknown encoding {label}"))
+}
+
+fn count(args: &CountArgs, walk: &WalkArgs) -> io::Result<()> {
+    let paths = if args.directory.is_empty() {
+        &args.paths
+    } else {
+        &args.directory
+    };
+    let options = Options {
+        threads: args.read.threads,
+        ..walk.options()?
+    };
+    let res = match &args.rev {
+        Some(rev) => lc::count_rev(paths, rev, &options).map_err(io::Error::other)?,
+        None => lc::count_paths(paths, &options),
+    };
+    let written = output::write(
+        &mut io::stdout().lock(),
+        &res,
+        &output::Options {
+            format: walk.format,
+            files: args.files,
+            by_extension: args.by_extension,
+            roots: args.per_root.then(|| paths.clone()),
+            sort: args.sort,
+            complexity: args.complexity || args.sort == Some(output::Column::Complexity),
+            tree: args.tree.then(|| output::Tree {
+                roots: paths.clone(),
+                depth: args.depth,
+            }),
+        },
     );
-    let mut languages: Vec<_> = res.languages.into_iter().collect();
+    written.and_then(|()| args.read.check_errors(&[&res]))
+}
 
-    languages.sort_by_key(|e| e.1);
+/// Counts one side of a diff, either a path on disk or a revision of the repository
+/// containing `path`. Paths are made relative to the side, so both sides compare alike.
+fn count_side(side: &str, path: &Path, options: &Options) -> io::Result<CountResult> {
+    let (mut res, root) = if Path::new(side).exists() {
+        (lc::count_paths(&[side], options), Path::new(side))
+    } else {
+        let res = lc::count_rev(&[path], side, options).map_err(io::Error::other)?;
+        (res, path)
+    };
+    res.strip_prefix(root);
+    Ok(res)
+}
 
-    for (lang, count) in languages {
-        let name = if let Some(lang) = LANGUAGES.get(&lang) {
-            lang.name
-        } else {
-            &lang
+fn diff(args: &DiffArgs, walk: &WalkArgs) -> io::Result<()> {
+    let options = Options {
+        threads: args.read.threads,
+        ..walk.options()?
+    };
+    let old = count_side(&args.old, &args.path, &options)?;
+    let new = count_side(&args.new, &args.path, &options)?;
+    output::warn_errors(&old)?;
+    output::warn_errors(&new)?;
+    output::write_diff(
+        &mut io::stdout().lock(),
+        &lc::diff(&old, &new),
+        walk.format,
+        args.files,
+    )?;
+    args.read.check_errors(&[&old, &new])
+}
+
+fn history(args: &HistoryArgs, walk: &WalkArgs) -> io::Result<()> {
+    let options = walk.options()?;
+    let snapshots = lc::history(&args.path, &args.rev, args.every.into(), &options)
+        .map_err(io::Error::other)?;
+    output::write_history(&mut io::stdout().lock(), &snapshots, walk.format)
+}
+
+fn blame(args: &BlameArgs, walk: &WalkArgs) -> io::Result<()> {
+    let options = walk.options()?;
+    let res = lc::blame(&args.path, &args.rev, &options).map_err(io::Error::other)?;
+    output::write_blame(&mut io::stdout().lock(), &res, walk.format)
+}
+
+fn run(args: Args) -> io::Result<()> {
+    match &args.command {
+        Some(Command::Diff(diff_args)) => diff(diff_args, &args.walk),
+        Some(Command::History(history_args)) => history(history_args, &args.walk),
+        Some(Command::Blame(blame_args)) => blame(blame_args, &args.walk),
+        None => count(&args.count, &args.walk),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lc::EntryError;
+
+    use super::*;
+
+    #[test]
+    fn strict_fails_listing_paths_that_cant_be_read() {
+        let res = CountResult {
+            errors: vec![
+                EntryError {
+                    path: Some(PathBuf::from("a.rs")),
+                    message: "Permission denied".to_string(),
+                },
+                EntryError {
+                    path: None,
+                    message: "loop detected".to_string(),
+                },
+            ],
+            ..CountResult::default()
         };
-        println!("{}: {}", name, count);
+        let read = |args: &[&str]| Args::parse_from(args).count.read;
+        assert!(read(&["lc"]).check_errors(&[&res]).is_ok());
+        let strict = read(&["lc", "--strict"]);
+        assert!(strict.check_errors(&[&CountResult::default()]).is_ok());
+        let err = strict.check_errors(&[&res]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2 paths couldn't be walked or read:\n  a.rs: Permission denied\n  loop detected"
+        );
     }
 
-    println!("Total: {}", res.total);
-}
\ No newline at end of file
+    #[test]
+    fn only_commands_counting_files_take_threads_and_strict() {
+        let args = Args::parse_from(["lc", "diff", "a", "b", "-j", "2", "--strict"]);
+        let Some(Command::Diff(diff)) = args.command else {
+            panic!("expected diff");
+        };
+        assert_eq!((diff.read.threads, diff.read.strict), (2, true));
+        assert!(Args::try_parse_from(["lc", "blame", "--strict"]).is_err());
+        assert!(Args::try_parse_from(["lc", "history", "-j", "2"]).is_err());
+    }
+
+    #[test]
+    fn directory_conflicts_with_paths() {
+        let paths = |args: &[&str]| {
+            let count = Args::parse_from(args).count;
+            (count.paths, count.directory)
+        };
+        assert_eq!(paths(&["lc", "-d", "src"]).1, [PathBuf::from("src")]);
+        assert_eq!(paths(&["lc", "a", "b"]).0, [Path::new("a"), Path::new("b")]);
+        assert!(Args::try_parse_from(["lc", "-d", "src", "tests"]).is_err());
+    }
+
+    #[test]
+    fn no_ignore_flags_turn_off_their_layer() {
+        let layers = |args: &[&str]| {
+            let options = Args::parse_from(args).walk.options().unwrap();
+            [
+                options.gitignore,
+                options.git_exclude,
+                options.git_global,
+                options.dot_ignore,
+                options.lcignore,
+            ]
+        };
+        assert_eq!(layers(&["lc"]), [true; 5]);
+        assert_eq!(layers(&["lc", "--no-ignore"]), [false; 5]);
+        let flags = [
+            "--no-ignore-vcs",
+            "--no-ignore-exclude",
+            "--no-ignore-global",
+            "--no-ignore-dot",
+            "--no-ignore-lc",
+        ];
+        for (i, flag) in flags.into_iter().enumerate() {
+            let mut expected = [true; 5];
+            expected[i] = false;
+            assert_eq!(layers(&["lc", flag]), expected, "{flag}");
+        }
+    }
+}
@@ -0,0 +1,666 @@
+//! Rendering of count results.
+//!
+//! The JSON and YAML formats share one schema:
+//!
+//! ```json
+//! {
+//!   "languages": [
+//!     { "language": "Rust", "code": 120, "comments": 14, "docs": 6, "blanks": 20, "lines": 160 }
+//!   ],
+//!   "files": [
+//!     { "path": "src/main.rs", "language": "Rust", "code": 120, "comments": 14, "docs": 6, "blanks": 20, "lines": 160 }
+//!   ],
+//!   "total": { "language": "Total", "code": 120, "comments": 14, "docs": 6, "blanks": 20, "lines": 160 }
+//! }
+//! ```
+//!
+//! `files` is only present when listing files and `roots` only when reporting the total
+//! of each path counted, with `path` set and `language` being `Total`. When grouping by
+//! extension `language` holds the file extension instead of the language name. Rows have a
+//! `complexity` after `code` when reporting complexity.
+//!
+//! CSV and Markdown output have one row per language with the columns
+//! `language`, `code`, `comments`, `docs`, `blanks` and `lines`, followed by a `Total` row.
+//! When listing files there is one row per file instead, with an additional leading
+//! `path` column. The total of each path counted is added before the `Total` row when
+//! requested, and a `complexity` column after `code` when reporting complexity.
+//!
+//! Files that weren't counted, like binaries and generated files, are listed under
+//! `skipped` with their `path` and `reason` in JSON and YAML, and in a table of their own
+//! after the counts in text and Markdown output. Likewise paths that couldn't be walked or
+//! read are listed under `errors` with their `path`, if known, and `error`.
+
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use clap::ValueEnum;
+use lc::{CountResult, EntryError, Skipped, Stats};
+use serde::Serialize;
+
+mod blame;
+mod diff;
+mod history;
+mod tree;
+
+pub use blame::write_blame;
+pub use diff::write_diff;
+pub use history::write_history;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Aligned columns for reading in a terminal
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Yaml,
+    Markdown,
+}
+
+/// A column to sort rows by. Text columns sort ascending, counts sort descending.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    Path,
+    Language,
+    Code,
+    Complexity,
+    Comments,
+    Docs,
+    Blanks,
+    Lines,
+}
+
+#[derive(Serialize)]
+struct Row {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    language: String,
+    code: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    complexity: Option<usize>,
+    comments: usize,
+    docs: usize,
+    blanks: usize,
+    lines: usize,
+}
+
+impl Row {
+    fn new(path: Option<String>, language: String, stats: &Stats) -> Self {
+        Self {
+            path,
+            language,
+            code: stats.code,
+            complexity: None,
+            comments: stats.comments,
+            docs: stats.docs,
+            blanks: stats.blanks,
+            lines: stats.lines(),
+        }
+    }
+
+    /// Adds the complexity of `stats` if `enabled`.
+    fn with_complexity(mut self, stats: &Stats, enabled: bool) -> Self {
+        self.complexity = enabled.then_some(stats.complexity);
+        self
+    }
+
+    fn fields(&self, with_path: bool) -> Vec<String> {
+        let path = with_path.then(|| self.path.clone().unwrap_or_default());
+        path.into_iter()
+            .chain([self.language.clone(), self.code.to_string()])
+            .chain(self.complexity.map(|complexity| complexity.to_string()))
+            .chain([
+                self.comments.to_string(),
+                self.docs.to_string(),
+                self.blanks.to_string(),
+                self.lines.to_string(),
+            ])
+            .collect()
+    }
+
+    fn count(&self, column: Column) -> Option<usize> {
+        match column {
+            Column::Path | Column::Language => None,
+            Column::Code => Some(self.code),
+            Column::Complexity => self.complexity,
+            Column::Comments => Some(self.comments),
+            Column::Docs => Some(self.docs),
+            Column::Blanks => Some(self.blanks),
+            Column::Lines => Some(self.lines),
+        }
+    }
+}
+
+fn sort_rows(rows: &mut [Row], column: Column) {
+    rows.sort_by(|a, b| match column {
+        Column::Path => a
+            .path
+            .cmp(&b.path)
+            .then_with(|| a.language.cmp(&b.language)),
+        Column::Language => a
+            .language
+            .cmp(&b.language)
+            .then_with(|| a.path.cmp(&b.path)),
+        _ => b
+            .count(column)
+            .cmp(&a.count(column))
+            .then_with(|| a.path.cmp(&b.path))
+            .then_with(|| a.language.cmp(&b.language)),
+    });
+}
+
+const HEADER: [&str; 6] = ["Language", "Code", "Comments", "Docs", "Blanks", "Lines"];
+
+/// A file that wasn't counted.
+#[derive(Serialize)]
+struct SkippedRow {
+    path: String,
+    reason: String,
+}
+
+impl SkippedRow {
+    fn all(skipped: &[Skipped]) -> Vec<Self> {
+        skipped
+            .iter()
+            .map(|skip| Self {
+                path: skip.path.display().to_string(),
+                reason: skip.reason.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// A path that couldn't be walked or read.
+#[derive(Serialize)]
+struct ErrorRow {
+    path: Option<String>,
+    error: String,
+}
+
+impl ErrorRow {
+    fn all(errors: &[EntryError]) -> Vec<Self> {
+        errors
+            .iter()
+            .map(|err| Self {
+                path: err.path.as_ref().map(|path| path.display().to_string()),
+                error: err.message.clone(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    #[serde(skip)]
+    by_extension: bool,
+    languages: Vec<Row>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<Vec<Row>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    roots: Option<Vec<Row>>,
+    total: Row,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped: Vec<SkippedRow>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<ErrorRow>,
+}
+
+impl Report {
+    fn new(res: &CountResult, options: &Options) -> Self {
+        let row = |path, language, stats: &Stats| {
+            Row::new(path, language, stats).with_complexity(stats, options.complexity)
+        };
+        let by_extension = options.by_extension.then(|| res.by_extension());
+        let mut languages: Vec<_> = by_extension
+            .as_ref()
+            .unwrap_or(&res.languages)
+            .iter()
+            .map(|(name, stats)| row(None, name.clone(), stats))
+            .collect();
+        match options.sort {
+            Some(column) => sort_rows(&mut languages, column),
+            None => languages.sort_by(|a, b| {
+                a.code
+                    .cmp(&b.code)
+                    .then_with(|| a.language.cmp(&b.language))
+            }),
+        }
+
+        let files = options.files.then(|| {
+            let mut files: Vec<_> = res
+                .files
+                .iter()
+                .map(|file| {
+                    let path = file.path.display().to_string();
+                    row(Some(path), file.language.clone(), &file.stats)
+                })
+                .collect();
+            sort_rows(&mut files, options.sort.unwrap_or(Column::Path));
+            files
+        });
+
+        let roots = options.roots.as_ref().map(|roots| {
+            roots
+                .iter()
+                .map(|root| {
+                    let path = root.display().to_string();
+                    row(Some(path), "Total".to_string(), &res.total_under(root))
+                })
+                .collect()
+        });
+
+        Self {
+            by_extension: options.by_extension,
+            languages,
+            files,
+            roots,
+            total: row(None, "Total".to_string(), &res.total),
+            skipped: SkippedRow::all(&res.skipped),
+            errors: ErrorRow::all(&res.errors),
+        }
+    }
+
+    /// The flat table used by the text, CSV and Markdown formats.
+    fn table(&self) -> Table {
+        let with_path = self.files.is_some() || self.roots.is_some();
+        let mut header: Vec<_> = with_path
+            .then_some("Path")
+            .into_iter()
+            .chain(HEADER)
+            .map(String::from)
+            .collect();
+        if self.by_extension && !with_path {
+            header[0] = "Extension".to_string();
+        }
+        let text_columns = 1 + usize::from(with_path);
+        if self.total.complexity.is_some() {
+            header.insert(text_columns + 1, "Complexity".to_string());
+        }
+        let rows = self
+            .files
+            .as_ref()
+            .unwrap_or(&self.languages)
+            .iter()
+            .chain(self.roots.iter().flatten())
+            .chain([&self.total])
+            .map(|row| row.fields(with_path))
+            .collect();
+        Table {
+            header,
+            text_columns,
+            rows,
+        }
+    }
+}
+
+/// The tables of skipped files and of paths that couldn't be walked or read, if there are
+/// any.
+fn problem_tables(skipped: &[SkippedRow], errors: &[ErrorRow]) -> Vec<Table> {
+    let skipped = Table {
+        header: vec!["Skipped".to_string(), "Reason".to_string()],
+        text_columns: 2,
+        rows: skipped
+            .iter()
+            .map(|skip| vec![skip.path.clone(), skip.reason.clone()])
+            .collect(),
+    };
+    let errors = Table {
+        header: vec!["Error".to_string(), "Reason".to_string()],
+        text_columns: 2,
+        rows: errors
+            .iter()
+            .map(|err| vec![err.path.clone().unwrap_or_default(), err.error.clone()])
+            .collect(),
+    };
+    [skipped, errors]
+        .into_iter()
+        .filter(|table| !table.rows.is_empty())
+        .collect()
+}
+
+/// Writes the tables of skipped files and of paths that couldn't be walked or read, which
+/// follow the counts in the text and Markdown formats. CSV has room for a single table
+/// only, so they're written to stderr instead.
+fn write_problems(
+    out: &mut impl Write,
+    skipped: &[SkippedRow],
+    errors: &[ErrorRow],
+    format: Format,
+) -> io::Result<()> {
+    let tables = problem_tables(skipped, errors);
+    match format {
+        Format::Text | Format::Markdown => {
+            for table in tables {
+                writeln!(out)?;
+                table.write(out, format)?;
+            }
+            Ok(())
+        }
+        Format::Csv => warn(tables),
+        Format::Json | Format::Yaml => Ok(()),
+    }
+}
+
+/// Writes `tables` to stderr.
+fn warn(tables: Vec<Table>) -> io::Result<()> {
+    let mut err = io::stderr().lock();
+    for (i, table) in tables.into_iter().enumerate() {
+        if i > 0 {
+            writeln!(err)?;
+        }
+        table.write(&mut err, Format::Text)?;
+    }
+    Ok(())
+}
+
+/// Writes the paths of `res` that couldn't be walked or read to stderr, for outputs that
+/// don't list them.
+pub fn warn_errors(res: &CountResult) -> io::Result<()> {
+    warn(problem_tables(&[], &ErrorRow::all(&res.errors)))
+}
+
+/// A table of text columns followed by count columns.
+struct Table {
+    header: Vec<String>,
+    /// How many of the leading columns hold text.
+    text_columns: usize,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn write(&self, out: &mut impl Write, format: Format) -> io::Result<()> {
+        match format {
+            Format::Csv => {
+                let mut writer = csv::Writer::from_writer(out);
+                writer.write_record(
+                    self.header
+                        .iter()
+                        .map(|field| field.to_lowercase().replace(' ', "_")),
+                )?;
+                for row in &self.rows {
+                    writer.write_record(row)?;
+                }
+                writer.flush()?;
+            }
+            Format::Markdown => {
+                let align: Vec<_> = (0..self.header.len())
+                    .map(|i| {
+                        if i < self.text_columns {
+                            ":---"
+                        } else {
+                            "---:"
+                        }
+                    })
+                    .collect();
+                writeln!(out, "| {} |", self.header.join(" | "))?;
+                writeln!(out, "|{}|", align.join("|"))?;
+                for row in &self.rows {
+                    writeln!(out, "| {} |", row.join(" | "))?;
+                }
+            }
+            // JSON and YAML have no table, see `write_report`.
+            _ => {
+                let mut widths: Vec<_> = (0..self.header.len())
+                    .map(|i| if i < self.text_columns { 20 } else { 10 })
+                    .collect();
+                for row in [&self.header].into_iter().chain(&self.rows) {
+                    for (width, field) in widths.iter_mut().zip(row) {
+                        *width = (*width).max(field.len());
+                    }
+                }
+                for row in [&self.header].into_iter().chain(&self.rows) {
+                    let line: Vec<_> = row
+                        .iter()
+                        .zip(&widths)
+                        .enumerate()
+                        .map(|(i, (field, width))| {
+                            if i < self.text_columns {
+                                format!("{field:<width$}")
+                            } else {
+                                format!("{field:>width$}")
+                            }
+                        })
+                        .collect();
+                    writeln!(out, "{}", line.join(" ").trim_end())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes `report` as JSON or YAML, or `table` in the other formats.
+fn write_report(
+    out: &mut impl Write,
+    format: Format,
+    report: &impl Serialize,
+    table: impl FnOnce() -> Table,
+) -> io::Result<()> {
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, report).map_err(to_io)?;
+            writeln!(out)
+        }
+        Format::Yaml => serde_yaml_ng::to_writer(out, report).map_err(to_io),
+        Format::Text | Format::Csv | Format::Markdown => table().write(out, format),
+    }
+}
+
+fn to_io(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::other(err)
+}
+
+/// What to include in the output and how to present it.
+pub struct Options {
+    pub format: Format,
+    /// List every file instead of only the totals per language.
+    pub files: bool,
+    /// Group the totals by file extension instead of by language.
+    pub by_extension: bool,
+    /// Paths to report subtotals for.
+    pub roots: Option<Vec<PathBuf>>,
+    pub sort: Option<Column>,
+    /// Report the complexity next to the code lines.
+    pub complexity: bool,
+    /// Show the totals of each directory instead of those of each language.
+    pub tree: Option<Tree>,
+}
+
+/// Which directories to show the totals of.
+pub struct Tree {
+    /// The paths counted, whose directories are shown.
+    pub roots: Vec<PathBuf>,
+    /// How many levels of directories to show below each root, all if `None`.
+    pub depth: Option<usize>,
+}
+
+pub fn write(out: &mut impl Write, res: &CountResult, options: &Options) -> io::Result<()> {
+    if let Some(tree) = &options.tree {
+        return tree::write_tree(out, res, tree, options.format, options.complexity);
+    }
+    let report = Report::new(res, options);
+    write_report(out, options.format, &report, || report.table())?;
+    write_problems(out, &report.skipped, &report.errors, options.format)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use lc::FileCount;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn result() -> CountResult {
+        let mut res = CountResult::default();
+        res.add(FileCount {
+            path: PathBuf::from("src/main.rs"),
+            language: "Rust".to_string(),
+            stats: Stats {
+                code: 3,
+                comments: 2,
+                docs: 1,
+                blanks: 1,
+                complexity: 4,
+            },
+            embedded: BTreeMap::new(),
+        });
+        res
+    }
+
+    fn options(format: Format) -> Options {
+        Options {
+            format,
+            files: false,
+            by_extension: false,
+            roots: None,
+            sort: None,
+            complexity: false,
+            tree: None,
+        }
+    }
+
+    fn render(options: &Options) -> String {
+        let mut out = Vec::new();
+        write(&mut out, &result(), options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn json_and_yaml_share_a_schema() {
+        let row = json!({
+            "language": "Rust", "code": 3, "comments": 2, "docs": 1, "blanks": 1, "lines": 7
+        });
+        let total = json!({
+            "language": "Total", "code": 3, "comments": 2, "docs": 1, "blanks": 1, "lines": 7
+        });
+        let json: Value = serde_json::from_str(&render(&options(Format::Json))).unwrap();
+        assert_eq!(json, json!({ "languages": [row], "total": total }));
+        let yaml: Value = serde_yaml_ng::from_str(&render(&options(Format::Yaml))).unwrap();
+        assert_eq!(yaml, json);
+
+        let options = Options {
+            files: true,
+            complexity: true,
+            ..options(Format::Json)
+        };
+        let json: Value = serde_json::from_str(&render(&options)).unwrap();
+        assert_eq!(
+            json["files"],
+            json!([{
+                "path": "src/main.rs", "language": "Rust", "code": 3, "complexity": 4,
+                "comments": 2, "docs": 1, "blanks": 1, "lines": 7
+            }])
+        );
+    }
+
+    #[test]
+    fn csv_has_a_row_per_language_and_a_total() {
+        assert_eq!(
+            render(&options(Format::Csv)),
+            "language,code,comments,docs,blanks,lines\n\
+             Rust,3,2,1,1,7\n\
+             Total,3,2,1,1,7\n"
+        );
+        let options = Options {
+            files: true,
+            complexity: true,
+            ..options(Format::Csv)
+        };
+        assert_eq!(
+            render(&options),
+            "path,language,code,complexity,comments,docs,blanks,lines\n\
+             src/main.rs,Rust,3,4,2,1,1,7\n\
+             ,Total,3,4,2,1,1,7\n"
+        );
+    }
+
+    #[test]
+    fn tree_has_complexity_after_code() {
+        let options = Options {
+            complexity: true,
+            tree: Some(Tree {
+                roots: vec![PathBuf::from("src")],
+                depth: None,
+            }),
+            ..options(Format::Csv)
+        };
+        assert_eq!(
+            render(&options),
+            "path,depth,code,complexity,comments,docs,blanks,lines\n\
+             src,0,3,4,2,1,1,7\n\
+             Total,,3,4,2,1,1,7\n"
+        );
+        let text = render(&Options {
+            format: Format::Text,
+            ..options
+        });
+        assert!(
+            text.starts_with("Path") && text.contains("Code Complexity"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn sorts_text_ascending_and_counts_descending() {
+        let row = |path: &str, language: &str, code| {
+            let stats = Stats {
+                code,
+                ..Stats::default()
+            };
+            Row::new(Some(path.to_string()), language.to_string(), &stats)
+        };
+        let mut rows = vec![
+            row("b.rs", "Rust", 1),
+            row("c.py", "Python", 2),
+            row("a.rs", "Rust", 2),
+        ];
+        let mut order = |column| {
+            sort_rows(&mut rows, column);
+            rows.iter()
+                .map(|row| row.path.clone().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(order(Column::Path), ["a.rs", "b.rs", "c.py"]);
+        // Ties are broken by path.
+        assert_eq!(order(Column::Language), ["c.py", "a.rs", "b.rs"]);
+        assert_eq!(order(Column::Code), ["a.rs", "c.py", "b.rs"]);
+        assert_eq!(order(Column::Lines), ["a.rs", "c.py", "b.rs"]);
+    }
+
+    #[test]
+    fn groups_by_extension() {
+        let mut res = CountResult::default();
+        for (path, language, code) in [("a.h", "C", 1), ("b.c", "C", 2), ("c.c", "C", 3)] {
+            res.add(FileCount {
+                path: PathBuf::from(path),
+                language: language.to_string(),
+                stats: Stats {
+                    code,
+                    ..Stats::default()
+                },
+                embedded: BTreeMap::new(),
+            });
+        }
+        assert_eq!(res.languages["C"].code, 6);
+        let extensions = res.by_extension();
+        assert_eq!((extensions["h"].code, extensions["c"].code), (1, 5));
+
+        let options = Options {
+            by_extension: true,
+            ..options(Format::Csv)
+        };
+        let mut out = Vec::new();
+        write(&mut out, &res, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "extension,code,comments,docs,blanks,lines\n\
+             h,1,0,0,0,1\n\
+             c,5,0,0,0,5\n\
+             Total,6,0,0,0,6\n"
+        );
+    }
+}
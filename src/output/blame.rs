@@ -0,0 +1,121 @@
+//! Rendering of lines attributed to authors.
+//!
+//! The JSON and YAML formats have one entry per author, owning the most code first, with
+//! the rows of the count schema:
+//!
+//! ```json
+//! {
+//!   "authors": [
+//!     {
+//!       "name": "Jane Doe",
+//!       "email": "jane@example.com",
+//!       "languages": [
+//!         { "language": "Rust", "code": 120, "comments": 14, "docs": 6, "blanks": 20, "lines": 160 }
+//!       ],
+//!       "total": { "language": "Total", "code": 120, "comments": 14, "docs": 6, "blanks": 20, "lines": 160 }
+//!     }
+//!   ],
+//!   "total": { "language": "Total", "code": 120, "comments": 14, "docs": 6, "blanks": 20, "lines": 160 }
+//! }
+//! ```
+//!
+//! The other formats have one row per author and language with the columns `author`,
+//! `language`, `code`, `comments`, `docs`, `blanks` and `lines`, followed by a `Total` row.
+//! Authors are written as `name <email>`. Skipped files are listed like in the count
+//! output.
+
+use std::{
+    cmp::Reverse,
+    io::{self, Write},
+};
+
+use lc::{AuthorCount, BlameResult};
+use serde::Serialize;
+
+use super::{write_problems, write_report, Format, Row, SkippedRow, Table, HEADER};
+
+#[derive(Serialize)]
+struct Author {
+    name: String,
+    email: String,
+    languages: Vec<Row>,
+    total: Row,
+}
+
+impl Author {
+    fn new(count: &AuthorCount) -> Self {
+        let mut languages: Vec<_> = count
+            .languages
+            .iter()
+            .map(|(name, stats)| Row::new(None, name.clone(), stats))
+            .collect();
+        languages.sort_by(|a, b| {
+            b.code
+                .cmp(&a.code)
+                .then_with(|| a.language.cmp(&b.language))
+        });
+        Self {
+            name: count.author.name.clone(),
+            email: count.author.email.clone(),
+            languages,
+            total: Row::new(None, "Total".to_string(), &count.total),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    authors: Vec<Author>,
+    total: Row,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped: Vec<SkippedRow>,
+}
+
+impl Report {
+    fn new(res: &BlameResult) -> Self {
+        let mut authors: Vec<_> = res.authors.iter().map(Author::new).collect();
+        authors.sort_by_key(|author| Reverse(author.total.code));
+        Self {
+            authors,
+            total: Row::new(None, "Total".to_string(), &res.total),
+            skipped: SkippedRow::all(&res.skipped),
+        }
+    }
+
+    fn table(&self) -> Table {
+        let header: Vec<_> = ["Author"]
+            .into_iter()
+            .chain(HEADER)
+            .map(String::from)
+            .collect();
+        let rows = self
+            .authors
+            .iter()
+            .flat_map(|author| {
+                let name = format!("{} <{}>", author.name, author.email);
+                author.languages.iter().map(move |row| {
+                    [name.clone()]
+                        .into_iter()
+                        .chain(row.fields(false))
+                        .collect()
+                })
+            })
+            .chain([[String::new()]
+                .into_iter()
+                .chain(self.total.fields(false))
+                .collect()])
+            .collect();
+        Table {
+            text_columns: header.len() - 5,
+            header,
+            rows,
+        }
+    }
+}
+
+/// Writes the lines owned by each author per language, followed by the total.
+pub fn write_blame(out: &mut impl Write, res: &BlameResult, format: Format) -> io::Result<()> {
+    let report = Report::new(res);
+    write_report(out, format, &report, || report.table())?;
+    write_problems(out, &report.skipped, &[], format)
+}
@@ -0,0 +1,181 @@
+//! Rendering of diff results.
+//!
+//! The JSON and YAML formats have one row per language, per file when listing files and
+//! for the total, each with the lines `added`, `removed` and the `net` change per category:
+//!
+//! ```json
+//! {
+//!   "languages": [
+//!     {
+//!       "language": "Rust",
+//!       "added": { "code": 12, "comments": 2, "docs": 1, "blanks": 3, "lines": 18 },
+//!       "removed": { "code": 4, "comments": 0, "docs": 0, "blanks": 1, "lines": 5 },
+//!       "net": { "code": 8, "comments": 2, "docs": 1, "blanks": 2, "lines": 13 }
+//!     }
+//!   ],
+//!   "total": { "language": "Total", "added": { ... }, "removed": { ... }, "net": { ... } }
+//! }
+//! ```
+//!
+//! The other formats have columns for the code, comment and documentation lines added and
+//! removed, and the net change in lines.
+
+use std::io::{self, Write};
+
+use lc::{Delta, DiffResult, Stats};
+use serde::Serialize;
+
+use super::{write_report, Format, Table};
+
+#[derive(Serialize)]
+struct Counts<T> {
+    code: T,
+    comments: T,
+    docs: T,
+    blanks: T,
+    lines: T,
+}
+
+impl From<Stats> for Counts<usize> {
+    fn from(stats: Stats) -> Self {
+        Self {
+            code: stats.code,
+            comments: stats.comments,
+            docs: stats.docs,
+            blanks: stats.blanks,
+            lines: stats.lines(),
+        }
+    }
+}
+
+impl Counts<isize> {
+    fn net(delta: &Delta) -> Self {
+        let net = |added: usize, removed: usize| added as isize - removed as isize;
+        let (added, removed) = (delta.added, delta.removed);
+        Self {
+            code: net(added.code, removed.code),
+            comments: net(added.comments, removed.comments),
+            docs: net(added.docs, removed.docs),
+            blanks: net(added.blanks, removed.blanks),
+            lines: net(added.lines(), removed.lines()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Row {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    language: String,
+    added: Counts<usize>,
+    removed: Counts<usize>,
+    net: Counts<isize>,
+}
+
+impl Row {
+    fn new(path: Option<String>, language: String, delta: &Delta) -> Self {
+        Self {
+            path,
+            language,
+            added: delta.added.into(),
+            removed: delta.removed.into(),
+            net: Counts::net(delta),
+        }
+    }
+
+    fn fields(&self, with_path: bool) -> Vec<String> {
+        let path = with_path.then(|| self.path.clone().unwrap_or_default());
+        path.into_iter()
+            .chain([
+                self.language.clone(),
+                self.added.code.to_string(),
+                self.removed.code.to_string(),
+                self.added.comments.to_string(),
+                self.removed.comments.to_string(),
+                self.added.docs.to_string(),
+                self.removed.docs.to_string(),
+                format!("{:+}", self.net.lines),
+            ])
+            .collect()
+    }
+}
+
+const HEADER: [&str; 8] = [
+    "Language",
+    "Code Added",
+    "Code Removed",
+    "Comments Added",
+    "Comments Removed",
+    "Docs Added",
+    "Docs Removed",
+    "Net Lines",
+];
+
+#[derive(Serialize)]
+struct Report {
+    languages: Vec<Row>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<Vec<Row>>,
+    total: Row,
+}
+
+impl Report {
+    fn new(res: &DiffResult, files: bool) -> Self {
+        let mut languages: Vec<_> = res
+            .languages
+            .iter()
+            .map(|(name, delta)| Row::new(None, name.clone(), delta))
+            .collect();
+        languages.sort_by(|a, b| a.language.cmp(&b.language));
+
+        let files = files.then(|| {
+            res.files
+                .iter()
+                .map(|file| {
+                    let path = file.path.display().to_string();
+                    Row::new(Some(path), file.language.clone(), &file.delta)
+                })
+                .collect()
+        });
+
+        Self {
+            languages,
+            files,
+            total: Row::new(None, "Total".to_string(), &res.total),
+        }
+    }
+
+    fn table(&self) -> Table {
+        let with_path = self.files.is_some();
+        let header: Vec<_> = with_path
+            .then_some("Path")
+            .into_iter()
+            .chain(HEADER)
+            .map(String::from)
+            .collect();
+        let rows = self
+            .files
+            .as_ref()
+            .unwrap_or(&self.languages)
+            .iter()
+            .chain([&self.total])
+            .map(|row| row.fields(with_path))
+            .collect();
+        Table {
+            text_columns: header.len() - 7,
+            header,
+            rows,
+        }
+    }
+}
+
+/// Writes the changes per language, or per file if `files` is set, followed by the total.
+pub fn write_diff(
+    out: &mut impl Write,
+    res: &DiffResult,
+    format: Format,
+    files: bool,
+) -> io::Result<()> {
+    let report = Report::new(res, files);
+    write_report(out, format, &report, || report.table())
+}
@@ -0,0 +1,149 @@
+//! Rendering of line counts over history.
+//!
+//! The JSON and YAML formats have one entry per commit, oldest first, with the rows of the
+//! count schema:
+//!
+//! ```json
+//! {
+//!   "commits": [
+//!     {
+//!       "commit": "9fceb02d0ae598e95dc970b74767f19372d61af8",
+//!       "date": "2024-03-01T14:02:11+01:00",
+//!       "tags": ["v1.0.0"],
+//!       "languages": [
+//!         { "language": "Rust", "code": 120, "comments": 14, "docs": 6, "blanks": 20, "lines": 160 }
+//!       ],
+//!       "total": { "language": "Total", "code": 120, "comments": 14, "docs": 6, "blanks": 20, "lines": 160 }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! The other formats have one row per commit and language, followed by a `Total` row per
+//! commit, with the columns `commit`, `date`, `tags`, `language`, `code`, `comments`,
+//! `docs`, `blanks` and `lines`. Tags are separated by spaces.
+
+use std::io::{self, Write};
+
+use lc::Snapshot;
+use serde::Serialize;
+
+use super::{write_report, Format, Row, Table, HEADER};
+
+/// Formats a commit time as RFC 3339 in the committer's time zone.
+fn date(time: i64, offset: i32) -> String {
+    let local = time + i64::from(offset) * 60;
+    let (days, secs) = (local.div_euclid(86400), local.rem_euclid(86400));
+
+    // Converts days since the epoch to a civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}{sign}{:02}:{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        offset.abs() / 60,
+        offset.abs() % 60,
+    )
+}
+
+#[derive(Serialize)]
+struct Commit {
+    commit: String,
+    date: String,
+    tags: Vec<String>,
+    languages: Vec<Row>,
+    total: Row,
+}
+
+impl Commit {
+    fn new(snapshot: &Snapshot) -> Self {
+        let res = &snapshot.result;
+        let mut languages: Vec<_> = res
+            .languages
+            .iter()
+            .map(|(name, stats)| Row::new(None, name.clone(), stats))
+            .collect();
+        languages.sort_by(|a, b| a.language.cmp(&b.language));
+        Self {
+            commit: snapshot.commit.clone(),
+            date: date(snapshot.time, snapshot.offset),
+            tags: snapshot.tags.clone(),
+            languages,
+            total: Row::new(None, "Total".to_string(), &res.total),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    commits: Vec<Commit>,
+}
+
+impl Report {
+    fn table(&self) -> Table {
+        let header: Vec<_> = ["Commit", "Date", "Tags"]
+            .into_iter()
+            .chain(HEADER)
+            .map(String::from)
+            .collect();
+        let rows = self
+            .commits
+            .iter()
+            .flat_map(|commit| {
+                commit.languages.iter().chain([&commit.total]).map(|row| {
+                    [
+                        commit.commit.clone(),
+                        commit.date.clone(),
+                        commit.tags.join(" "),
+                    ]
+                    .into_iter()
+                    .chain(row.fields(false))
+                    .collect()
+                })
+            })
+            .collect();
+        Table {
+            text_columns: header.len() - 5,
+            header,
+            rows,
+        }
+    }
+}
+
+/// Writes the counts of each snapshot per language, followed by its total.
+pub fn write_history(
+    out: &mut impl Write,
+    snapshots: &[Snapshot],
+    format: Format,
+) -> io::Result<()> {
+    let report = Report {
+        commits: snapshots.iter().map(Commit::new).collect(),
+    };
+    write_report(out, format, &report, || report.table())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_dates_in_the_committers_time_zone() {
+        assert_eq!(date(0, 0), "1970-01-01T00:00:00+00:00");
+        assert_eq!(date(0, -300), "1969-12-31T19:00:00-05:00");
+        assert_eq!(date(-31536001, 90), "1969-01-01T01:29:59+01:30");
+        assert_eq!(date(951782400, -60), "2000-02-28T23:00:00-01:00");
+        assert_eq!(date(951782400, 0), "2000-02-29T00:00:00+00:00");
+    }
+}
@@ -0,0 +1,161 @@
+//! Rendering of per-directory rollups.
+//!
+//! The JSON and YAML formats list the directories under each path counted, each directory
+//! followed by its subdirectories, with their depth below the path counted:
+//!
+//! ```json
+//! {
+//!   "directories": [
+//!     { "path": ".", "depth": 0, "code": 120, "comments": 14, "docs": 6, "blanks": 20, "lines": 160 },
+//!     { "path": "./src", "depth": 1, "code": 100, "comments": 10, "docs": 6, "blanks": 16, "lines": 132 }
+//!   ],
+//!   "total": { "language": "Total", "code": 120, "comments": 14, "docs": 6, "blanks": 20, "lines": 160 }
+//! }
+//! ```
+//!
+//! CSV output has the same columns, followed by a `Total` row. Text and Markdown output
+//! indent each directory's name by its depth instead. Directories and the total have a
+//! `complexity` after `code` when reporting complexity. Skipped files and errors are listed
+//! like in the count output.
+
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use lc::{CountResult, Stats};
+use serde::Serialize;
+
+use super::{write_problems, write_report, ErrorRow, Format, Row, SkippedRow, Table, Tree, HEADER};
+
+#[derive(Serialize)]
+struct Directory {
+    path: String,
+    /// The name shown in the indented views.
+    #[serde(skip)]
+    name: String,
+    depth: usize,
+    code: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    complexity: Option<usize>,
+    comments: usize,
+    docs: usize,
+    blanks: usize,
+    lines: usize,
+}
+
+impl Directory {
+    fn new(root: &Path, path: &Path, stats: &Stats, complexity: bool) -> Self {
+        let depth = path.strip_prefix(root).unwrap().components().count();
+        let name = match path.file_name() {
+            Some(name) if depth > 0 => name.to_string_lossy().into_owned(),
+            _ => path.display().to_string(),
+        };
+        Self {
+            path: path.display().to_string(),
+            name,
+            depth,
+            code: stats.code,
+            complexity: complexity.then_some(stats.complexity),
+            comments: stats.comments,
+            docs: stats.docs,
+            blanks: stats.blanks,
+            lines: stats.lines(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    directories: Vec<Directory>,
+    total: Row,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped: Vec<SkippedRow>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<ErrorRow>,
+}
+
+impl Report {
+    fn new(res: &CountResult, tree: &Tree, complexity: bool) -> Self {
+        let directories = tree
+            .roots
+            .iter()
+            .flat_map(|root| {
+                res.directories(root, tree.depth)
+                    .into_iter()
+                    .map(|(path, stats)| Directory::new(root, &path, &stats, complexity))
+            })
+            .collect();
+        Self {
+            directories,
+            total: Row::new(None, "Total".to_string(), &res.total)
+                .with_complexity(&res.total, complexity),
+            skipped: SkippedRow::all(&res.skipped),
+            errors: ErrorRow::all(&res.errors),
+        }
+    }
+
+    fn table(&self, format: Format) -> Table {
+        let indented = format != Format::Csv;
+        let mut header: Vec<_> = HEADER.into_iter().map(String::from).collect();
+        header[0] = "Path".to_string();
+        if !indented {
+            header.insert(1, "Depth".to_string());
+        }
+        if self.total.complexity.is_some() {
+            let code = 1 + usize::from(!indented);
+            header.insert(code + 1, "Complexity".to_string());
+        }
+        let rows = self
+            .directories
+            .iter()
+            .map(|dir| {
+                let counts = [dir.comments, dir.docs, dir.blanks, dir.lines];
+                let path = if indented {
+                    // Markdown collapses regular spaces.
+                    let indent = if format == Format::Markdown {
+                        "&nbsp;&nbsp;"
+                    } else {
+                        "  "
+                    };
+                    format!("{}{}", indent.repeat(dir.depth), dir.name)
+                } else {
+                    dir.path.clone()
+                };
+                [path]
+                    .into_iter()
+                    .chain((!indented).then(|| dir.depth.to_string()))
+                    .chain([dir.code.to_string()])
+                    .chain(dir.complexity.map(|complexity| complexity.to_string()))
+                    .chain(counts.map(|count| count.to_string()))
+                    .collect()
+            })
+            .chain([{
+                let mut total = self.total.fields(false);
+                if !indented {
+                    total.insert(1, String::new());
+                }
+                total
+            }])
+            .collect();
+        Table {
+            text_columns: 1,
+            header,
+            rows,
+        }
+    }
+}
+
+/// Writes the line counts of the directories under each root, followed by the total, with
+/// their complexity if `complexity` is set.
+pub fn write_tree(
+    out: &mut impl Write,
+    res: &CountResult,
+    tree: &Tree,
+    format: Format,
+    complexity: bool,
+) -> io::Result<()> {
+    let report = Report::new(res, tree, complexity);
+    write_report(out, format, &report, || report.table(format))?;
+    write_problems(out, &report.skipped, &report.errors, format)
+}
@@ -0,0 +1,601 @@
+//! Classification of source lines into code, comments, documentation and blanks.
+
+use crate::{CommentSyntax, Language, StringSyntax};
+
+/// What a single line of source consists of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LineKind {
+    Code,
+    Comment,
+    Doc,
+    Blank,
+}
+
+/// What the scanner is inside of at the current position.
+#[derive(Debug, Clone, Copy)]
+enum State<'a> {
+    Code,
+    /// A block comment closed by `end`. Nested comments opened by `start` have to be
+    /// closed first, `depth` counts how many comments are open.
+    Comment {
+        doc: bool,
+        start: Option<&'a str>,
+        end: &'a str,
+        depth: usize,
+    },
+    /// A string closed by `end`, in which `\` escapes the next character if `escaped`.
+    Str {
+        end: &'a str,
+        escaped: bool,
+    },
+    /// A Rust raw string closed by `"` followed by the given number of `#`.
+    RawStr(usize),
+    /// A documentation string closed by the given token.
+    DocStr(&'a str),
+}
+
+/// What the line being scanned consists of so far.
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    /// The kind of the line if it turns out to be blank.
+    blank: LineKind,
+    nonblank: bool,
+    code: bool,
+    comment: bool,
+    doc: bool,
+    /// Whether the rest of the line is a line comment.
+    commented: bool,
+    /// Whether the last character of code was a `\`.
+    continues: bool,
+}
+
+impl Line {
+    /// A line starting in `state`.
+    fn new(state: State) -> Self {
+        let in_string = matches!(state, State::Str { .. } | State::RawStr(_));
+        Self {
+            // Blank lines inside a string are part of its value.
+            blank: if in_string {
+                LineKind::Code
+            } else if matches!(state, State::DocStr(_)) {
+                LineKind::Doc
+            } else {
+                LineKind::Blank
+            },
+            nonblank: false,
+            code: in_string,
+            comment: matches!(state, State::Comment { .. }),
+            doc: matches!(state, State::Comment { doc: true, .. } | State::DocStr(_)),
+            commented: false,
+            continues: false,
+        }
+    }
+
+    fn kind(&self) -> LineKind {
+        if !self.nonblank {
+            self.blank
+        } else if self.code {
+            LineKind::Code
+        } else if self.doc {
+            LineKind::Doc
+        } else if self.comment {
+            LineKind::Comment
+        } else {
+            LineKind::Blank
+        }
+    }
+}
+
+/// A token that changes the state of the scanner.
+enum Token<'a> {
+    LineComment {
+        doc: bool,
+    },
+    BlockComment {
+        doc: bool,
+        start: Option<&'a str>,
+        end: &'a str,
+    },
+    Str {
+        end: &'a str,
+        escaped: bool,
+    },
+    RawStr(usize),
+    DocStr(&'a str),
+    /// A complete character literal.
+    Char,
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The length of the character or byte literal at the start of `rest`, if there is one.
+fn char_literal(rest: &str) -> Option<usize> {
+    let prefix = usize::from(rest.starts_with("b'"));
+    let inner = rest[prefix..].strip_prefix('\'')?;
+    let len = if let Some(escape) = inner.strip_prefix('\\') {
+        2 + escape.get(1..)?.find('\'')?
+    } else {
+        inner.chars().next().filter(|c| *c != '\'')?.len_utf8()
+    };
+    inner[len..].starts_with('\'').then_some(prefix + len + 2)
+}
+
+/// The length of the start of the Rust raw string at the start of `rest` and the number
+/// of `#` it uses, if there is one.
+fn raw_string(rest: &str) -> Option<(usize, usize)> {
+    let inner = rest.strip_prefix("br").or_else(|| rest.strip_prefix('r'))?;
+    let hashes = inner.len() - inner.trim_start_matches('#').len();
+    inner[hashes..]
+        .starts_with('"')
+        .then_some((rest.len() - inner.len() + hashes + 1, hashes))
+}
+
+/// Finds the end of a string in `rest`, returning the index just past the closing token.
+fn string_end(rest: &str, end: &str, escaped: bool) -> Option<usize> {
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if rest[i..].starts_with(end) {
+            return Some(i + end.len());
+        }
+        if escaped && c == '\\' {
+            chars.next();
+        }
+    }
+    None
+}
+
+/// Whether a `for` between `before` and `after` on a line is that of a Rust trait, as in
+/// `impl A for B` or a bound like `for<'a> Fn(&'a str)`, rather than a loop.
+fn is_trait_for(before: &str, after: &str) -> bool {
+    after.trim_start().starts_with('<') || before.split(|c| !is_ident(c)).any(|w| w == "impl")
+}
+
+/// Classifies lines one at a time, keeping track of comments and strings spanning lines.
+pub(crate) struct Scanner<'a> {
+    language: &'a Language<'a>,
+    state: State<'a>,
+    /// How many brackets are open in code.
+    brackets: usize,
+    /// Whether the last line ended with a `\` continuing it on the next line.
+    continued: bool,
+    /// What the line being scanned consists of so far, until it ends.
+    line: Option<Line>,
+    /// The number of branches found in code so far.
+    pub branches: usize,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(language: &'a Language<'a>) -> Self {
+        Self {
+            language,
+            state: State::Code,
+            brackets: 0,
+            continued: false,
+            line: None,
+            branches: 0,
+        }
+    }
+
+    /// Whether the scanner is in code rather than in a comment or string.
+    pub fn in_code(&self) -> bool {
+        matches!(self.state, State::Code)
+    }
+
+    /// The length of the branch keyword or operator starting at `i` in `line`, if there is
+    /// one. Keywords have to be whole words, and `for` can't be that of a Rust trait.
+    /// Operators only count where an operand can't start, so `||` opening a closure like
+    /// `f(move || 0)` and `?` in a bound like `T: ?Sized` don't.
+    fn branch(&self, line: &str, i: usize) -> Option<usize> {
+        let rest = &line[i..];
+        let after_ident = line[..i].chars().next_back().is_some_and(is_ident);
+        let before = line[..i].trim_end();
+        let opens_operand = before.ends_with(['(', '[', '{', ',', '=', ';', ':', '+', '<'])
+            || matches!(
+                before.rsplit(|c| !is_ident(c)).next(),
+                Some("move" | "return")
+            );
+        self.language
+            .branches
+            .iter()
+            .filter(|branch| !branch.is_empty() && rest.starts_with(**branch))
+            .filter(|branch| {
+                if branch.starts_with(is_ident) {
+                    let after = &rest[branch.len()..];
+                    let trait_for = **branch == "for" && is_trait_for(before, after);
+                    !(after_ident || after.starts_with(is_ident) || trait_for)
+                } else {
+                    !opens_operand
+                }
+            })
+            .map(|branch| branch.len())
+            .max()
+    }
+
+    /// The start of the nested comments closed by `end`, which also open comments nested
+    /// in documentation comments closed by `end`.
+    fn nested_start(&self, end: &str) -> Option<&'a str> {
+        self.language.comments.iter().find_map(|sntx| match *sntx {
+            CommentSyntax::Nested(start, nested_end) if nested_end == end => Some(start),
+            _ => None,
+        })
+    }
+
+    /// The longest token starting at `i` in `line`, along with its length.
+    fn token(&self, line: &str, i: usize) -> Option<(usize, Token<'a>)> {
+        let rest = &line[i..];
+        let after_ident = line[..i].chars().next_back().is_some_and(is_ident);
+
+        let comments = self.language.comments.iter().filter_map(|sntx| {
+            let (start, token) = match *sntx {
+                CommentSyntax::LineStart(start) => (start, Token::LineComment { doc: false }),
+                CommentSyntax::DocLineStart(start) => {
+                    // Banners like `////` are regular comments.
+                    let banner = start.ends_with('/')
+                        && rest
+                            .strip_prefix(start)
+                            .is_some_and(|after| after.starts_with('/'));
+                    (start, Token::LineComment { doc: !banner })
+                }
+                CommentSyntax::Range(start, end) => (
+                    start,
+                    Token::BlockComment {
+                        doc: false,
+                        start: None,
+                        end,
+                    },
+                ),
+                CommentSyntax::Nested(start, end) => (
+                    start,
+                    Token::BlockComment {
+                        doc: false,
+                        start: Some(start),
+                        end,
+                    },
+                ),
+                CommentSyntax::DocRange(start, end) | CommentSyntax::NestedDoc(start, end) => {
+                    if !rest.starts_with(start) {
+                        return None;
+                    }
+                    // In `/**/` the end overlaps the start, making it a regular comment.
+                    let overlapping = rest
+                        .get(1..start.len() + end.len() - 1)
+                        .is_some_and(|head| head.contains(end));
+                    if overlapping {
+                        return None;
+                    }
+                    let nested = matches!(sntx, CommentSyntax::NestedDoc(..))
+                        .then(|| self.nested_start(end).unwrap_or(start));
+                    (
+                        start,
+                        Token::BlockComment {
+                            doc: true,
+                            start: nested,
+                            end,
+                        },
+                    )
+                }
+            };
+            rest.starts_with(start).then_some((start.len(), token))
+        });
+        let strings = self.language.strings.iter().filter_map(|sntx| match sntx {
+            StringSyntax::Escaped(start, end) => rest
+                .starts_with(start)
+                .then_some((start.len(), Token::Str { end, escaped: true })),
+            StringSyntax::Verbatim(start, end) => rest.starts_with(start).then_some((
+                start.len(),
+                Token::Str {
+                    end,
+                    escaped: false,
+                },
+            )),
+            StringSyntax::Char => char_literal(rest)
+                .filter(|_| !after_ident)
+                .map(|len| (len, Token::Char)),
+            // Only strings that are statements of their own are documentation.
+            StringSyntax::Doc(start, end) => rest
+                .starts_with(start)
+                .then_some((start.len(), Token::DocStr(end)))
+                .filter(|_| self.brackets == 0 && !self.continued && line[..i].trim().is_empty()),
+            StringSyntax::RawRust => raw_string(rest)
+                .filter(|_| !after_ident)
+                .map(|(len, hashes)| (len, Token::RawStr(hashes))),
+        });
+
+        // Empty tokens would never move the scanner forward.
+        comments
+            .chain(strings)
+            .filter(|(len, _)| *len > 0)
+            .reduce(|a, b| if b.0 > a.0 { b } else { a })
+    }
+
+    pub fn classify(&mut self, line: &str) -> LineKind {
+        self.scan(line);
+        self.end_line()
+    }
+
+    /// Scans `part` of the current line, see [`Scanner::end_line`]. Lines too long to be
+    /// kept in memory are scanned a part at a time, missing tokens split between parts.
+    pub fn scan(&mut self, part: &str) {
+        let mut current = self.line.take().unwrap_or_else(|| Line::new(self.state));
+        if !current.commented && !part.trim().is_empty() {
+            current.nonblank = true;
+            self.scan_code(part, &mut current);
+        }
+        self.line = Some(current);
+    }
+
+    /// Ends the current line, returning its kind.
+    pub fn end_line(&mut self) -> LineKind {
+        let current = self.line.take().unwrap_or_else(|| Line::new(self.state));
+        if current.nonblank {
+            self.continued = current.continues && matches!(self.state, State::Code);
+        }
+        current.kind()
+    }
+
+    /// Scans `line`, a non-blank part of the current line, noting what it consists of in
+    /// `current`.
+    fn scan_code(&mut self, line: &str, current: &mut Line) {
+        let mut i = 0;
+        while i < line.len() {
+            let rest = &line[i..];
+            match self.state {
+                State::Comment {
+                    doc: in_doc,
+                    start,
+                    end,
+                    depth,
+                } => {
+                    let opened = start.and_then(|start| Some((rest.find(start)?, start)));
+                    match (opened, rest.find(end)) {
+                        (Some((j, start)), closed) if closed.is_none_or(|k| j < k) => {
+                            i += j + start.len();
+                            self.state = State::Comment {
+                                doc: in_doc,
+                                start: Some(start),
+                                end,
+                                depth: depth + 1,
+                            };
+                        }
+                        (_, Some(k)) => {
+                            i += k + end.len();
+                            self.state = if depth > 1 {
+                                State::Comment {
+                                    doc: in_doc,
+                                    start,
+                                    end,
+                                    depth: depth - 1,
+                                }
+                            } else {
+                                State::Code
+                            };
+                        }
+                        (_, None) => break,
+                    }
+                }
+                State::DocStr(end) => match string_end(rest, end, true) {
+                    Some(j) => {
+                        i += j;
+                        self.state = State::Code;
+                    }
+                    None => break,
+                },
+                State::Str { end, escaped } => match string_end(rest, end, escaped) {
+                    Some(j) => {
+                        i += j;
+                        self.state = State::Code;
+                    }
+                    None => break,
+                },
+                State::RawStr(hashes) => {
+                    let end = rest
+                        .match_indices('"')
+                        .map(|(j, _)| j + 1)
+                        .find(|j| rest[*j..].bytes().take_while(|b| *b == b'#').count() >= hashes);
+                    match end {
+                        Some(j) => {
+                            i += j + hashes;
+                            self.state = State::Code;
+                        }
+                        None => break,
+                    }
+                }
+                State::Code => match self.token(line, i) {
+                    Some((len, token)) => {
+                        i += len;
+                        current.continues = false;
+                        match token {
+                            Token::LineComment { doc: is_doc } => {
+                                current.comment = true;
+                                current.doc |= is_doc;
+                                current.commented = true;
+                                break;
+                            }
+                            Token::BlockComment {
+                                doc: is_doc,
+                                start,
+                                end,
+                            } => {
+                                current.comment = true;
+                                current.doc |= is_doc;
+                                self.state = State::Comment {
+                                    doc: is_doc,
+                                    start,
+                                    end,
+                                    depth: 1,
+                                };
+                            }
+                            Token::Str { end, escaped } => {
+                                current.code = true;
+                                self.state = State::Str { end, escaped };
+                            }
+                            Token::RawStr(hashes) => {
+                                current.code = true;
+                                self.state = State::RawStr(hashes);
+                            }
+                            Token::DocStr(end) => {
+                                current.doc = true;
+                                self.state = State::DocStr(end);
+                            }
+                            Token::Char => current.code = true,
+                        }
+                    }
+                    None => match self.branch(line, i) {
+                        Some(len) => {
+                            current.code = true;
+                            self.branches += 1;
+                            i += len;
+                        }
+                        None => {
+                            let c = rest.chars().next().unwrap();
+                            match c {
+                                '(' | '[' | '{' => self.brackets += 1,
+                                ')' | ']' | '}' => self.brackets = self.brackets.saturating_sub(1),
+                                _ => {}
+                            }
+                            if !c.is_whitespace() {
+                                current.code = true;
+                                current.continues = c == '\\';
+                            }
+                            i += c.len_utf8();
+                        }
+                    },
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineKind::*, *};
+    use crate::LANGUAGES;
+
+    fn kinds(ext: &str, src: &str) -> Vec<LineKind> {
+        let mut scanner = Scanner::new(&LANGUAGES[ext]);
+        src.lines().map(|line| scanner.classify(line)).collect()
+    }
+
+    #[test]
+    fn byte_literal_quote_doesnt_open_string() {
+        assert_eq!(kinds("rs", "let q = b'\"';\n// comment"), [Code, Comment]);
+        assert_eq!(kinds("rs", "let q = '\"';\n// comment"), [Code, Comment]);
+    }
+
+    #[test]
+    fn lifetimes_arent_char_literals() {
+        let src = "fn f<'a>(x: &'a str, y: &'a str) {}\n// comment";
+        assert_eq!(kinds("rs", src), [Code, Comment]);
+    }
+
+    #[test]
+    fn raw_strings_hide_comments() {
+        let src = "let s = r#\"\n/* not a comment\n\"#;\n// comment";
+        assert_eq!(kinds("rs", src), [Code, Code, Code, Comment]);
+    }
+
+    #[test]
+    fn lua_comments_and_long_strings() {
+        let src = "-- comment\nlocal s = [[\n-- not a comment\n]] -- comment\n--[==[\nx = 1\n]==]";
+        assert_eq!(
+            kinds("lua", src),
+            [Comment, Code, Code, Code, Comment, Comment, Comment]
+        );
+        assert_eq!(kinds("lua", "x = 1 // 2\n/* y */"), [Code, Code]);
+    }
+
+    #[test]
+    fn doc_comments_nest() {
+        let src = "/** outer\n/* inner */\nstill doc */\nfn f() {}";
+        assert_eq!(kinds("rs", src), [Doc, Doc, Doc, Code]);
+        let src = "{-| outer\n{- inner -}\nstill doc -}\nx = 1";
+        assert_eq!(kinds("hs", src), [Doc, Doc, Doc, Code]);
+    }
+
+    #[test]
+    fn empty_doc_comment_isnt_doc() {
+        assert_eq!(kinds("rs", "/**/\nfn f() {}"), [Comment, Code]);
+        assert_eq!(kinds("c", "/***/\nint x;"), [Doc, Code]);
+    }
+
+    #[test]
+    fn banner_line_comment_isnt_doc() {
+        let src = "////// banner\n/// doc\n//!/ inner doc\n// comment";
+        assert_eq!(kinds("rs", src), [Comment, Doc, Doc, Comment]);
+    }
+
+    #[test]
+    fn long_lines_scan_in_linear_time() {
+        // Quadratic scanning would take seconds for a line this long.
+        let line = "let a = b * c / d; ".repeat(3_200);
+        for (ext, doc) in [("rs", "/// doc"), ("c", "/** doc */"), ("hs", "{-| doc -}")] {
+            assert_eq!(kinds(ext, &format!("{line}\n{doc}")), [Code, Doc]);
+        }
+    }
+
+    #[test]
+    fn docstrings_are_statements() {
+        let src = "\"\"\"Module doc.\"\"\"\ncursor.execute(\"\"\"\nSELECT 1\n\"\"\")";
+        assert_eq!(kinds("py", src), [Doc, Code, Code, Code]);
+        let src = "x = (1,\n\"\"\"not doc\"\"\")\ny = 1 + \\\n\"\"\"not doc\"\"\"";
+        assert_eq!(kinds("py", src), [Code, Code, Code, Code]);
+        let src = "def f():\n    \"\"\"Doc.\n\n    More.\n    \"\"\"";
+        assert_eq!(kinds("py", src), [Code, Doc, Doc, Doc, Doc]);
+    }
+
+    #[test]
+    fn empty_tokens_are_ignored() {
+        let language = Language::new("empty", &[CommentSyntax::LineStart("")])
+            .with_strings(&[StringSyntax::Escaped("", "")])
+            .with_branches(&[""]);
+        let mut scanner = Scanner::new(&language);
+        assert_eq!(scanner.classify("if x"), Code);
+        assert_eq!(scanner.branches, 0);
+    }
+
+    #[test]
+    fn branches_are_whole_words() {
+        let mut scanner = Scanner::new(&LANGUAGES["rs"]);
+        scanner.classify("if elsewhere && x { // if");
+        scanner.classify("let s = \"if\";");
+        assert_eq!(scanner.branches, 2);
+        // Closures without arguments aren't branches.
+        scanner.classify("let a = a.unwrap_or_else(|| 0) || v || w;");
+        scanner.classify("let f = || 0;");
+        scanner.classify("thread::spawn(move || {});");
+        scanner.classify("    || x");
+        assert_eq!(scanner.branches, 5);
+        // Neither are traits and bounds.
+        scanner.classify("fn f<T: ?Sized>(x: &T) {}");
+        scanner.classify("impl<T: ?Sized + Send> A for T {}");
+        scanner.classify("fn g<F>(f: F) where F: for<'a> Fn(&'a str) {}");
+        scanner.classify("let f = S { f: || 0, r: &&x };");
+        assert_eq!(scanner.branches, 5);
+        scanner.classify("for x in y { f(x)? }");
+        assert_eq!(scanner.branches, 7);
+    }
+
+    #[test]
+    fn parts_are_classified_like_lines() {
+        let mut scanner = Scanner::new(&LANGUAGES["rs"]);
+        scanner.scan("   ");
+        scanner.scan("/* a");
+        scanner.scan(" b");
+        assert_eq!(scanner.end_line(), Comment);
+        scanner.scan("*/ let s = \"");
+        scanner.scan("// c\"; // d");
+        scanner.scan(" \"");
+        assert_eq!(scanner.end_line(), Code);
+        assert_eq!(scanner.classify("// e"), Comment);
+    }
+
+    #[test]
+    fn block_comments_nest_where_allowed() {
+        let src = "/* a /* b */\nstill */\nlet x = 1";
+        assert_eq!(kinds("swift", src), [Comment, Comment, Code]);
+        let src = "/* a /* b */\nint x;";
+        assert_eq!(kinds("c", src), [Comment, Code]);
+    }
+}
@@ -0,0 +1,154 @@
+//! Detection of files that aren't worth counting, like binaries and generated code, and
+//! reporting of paths that couldn't be counted.
+
+use std::{fmt, path::PathBuf};
+
+/// Why a file wasn't counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The file contains NUL bytes and isn't UTF-16.
+    Binary,
+    /// The file says it's generated in a comment in its first lines.
+    Generated,
+    /// The file is named like a minified file, or it's of a language that gets minified and
+    /// has very long lines.
+    Minified,
+    /// The file isn't valid text in the encoding it was read as.
+    Encoding,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Binary => "binary",
+            Self::Generated => "generated",
+            Self::Minified => "minified",
+            Self::Encoding => "invalid encoding",
+        })
+    }
+}
+
+/// A file that wasn't counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skipped {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+}
+
+/// A path that couldn't be walked or read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryError {
+    /// The path the error happened at, if it's known.
+    pub path: Option<PathBuf>,
+    /// The description of the underlying error.
+    pub message: String,
+}
+
+/// Flattens an error of the walk into one error per path it happened at.
+pub(crate) fn walk_errors(err: ignore::Error, path: Option<PathBuf>, errors: &mut Vec<EntryError>) {
+    match err {
+        ignore::Error::Partial(errs) => {
+            for err in errs {
+                walk_errors(err, path.clone(), errors);
+            }
+        }
+        ignore::Error::WithPath { path, err } => walk_errors(*err, Some(path), errors),
+        ignore::Error::WithDepth { err, .. } => walk_errors(*err, path, errors),
+        err => {
+            let path = path.or_else(|| match &err {
+                ignore::Error::Loop { child, .. } => Some(child.clone()),
+                _ => None,
+            });
+            errors.push(EntryError {
+                path,
+                message: err.to_string(),
+            });
+        }
+    }
+}
+
+/// How many bytes are searched for NUL bytes, like git does.
+pub(crate) const BINARY_PREFIX: usize = 8000;
+/// How many lines at the start of a file are searched for generated markers.
+pub(crate) const GENERATED_LINES: usize = 10;
+const GENERATED_MARKERS: &[&str] = &[
+    "@generated",
+    "DO NOT EDIT",
+    "Code generated",
+    "<auto-generated",
+];
+/// Files with a line longer than this and lines this long on average are minified.
+const MINIFIED_MAX_LINE: usize = 1000;
+const MINIFIED_AVERAGE_LINE: usize = 200;
+
+pub(crate) fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_PREFIX)].contains(&0)
+}
+
+/// Whether `line`, one of the first [`GENERATED_LINES`] of a file, has a generated marker.
+/// Only comment lines with a marker mark the file as generated.
+pub(crate) fn is_generated_line(line: &str) -> bool {
+    GENERATED_MARKERS.iter().any(|marker| line.contains(marker))
+}
+
+pub(crate) fn is_minified_name(name: &str) -> bool {
+    name.contains(".min.")
+}
+
+/// The lengths of the lines of a file, added a line at a time.
+#[derive(Debug, Default)]
+pub(crate) struct LineLengths {
+    lines: usize,
+    longest: usize,
+    /// The length of all lines, including their line endings.
+    total: usize,
+}
+
+impl LineLengths {
+    pub(crate) fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// Adds a line `len` bytes long.
+    pub(crate) fn add(&mut self, len: usize) {
+        self.lines += 1;
+        self.longest = self.longest.max(len);
+        self.total += len + 1;
+    }
+
+    /// Whether the lines are as long as those of a minified file.
+    pub(crate) fn are_minified(&self) -> bool {
+        self.longest > MINIFIED_MAX_LINE && self.total / self.lines.max(1) > MINIFIED_AVERAGE_LINE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_generated_markers() {
+        assert!(is_generated_line(
+            "// Code generated by protoc-gen-go. DO NOT EDIT."
+        ));
+        assert!(is_generated_line("# @generated by a tool"));
+        assert!(!is_generated_line("// generated by hand"));
+    }
+
+    #[test]
+    fn detects_minified_line_lengths() {
+        let lengths = |lines: &[usize]| {
+            let mut lengths = LineLengths::default();
+            for len in lines {
+                lengths.add(*len);
+            }
+            lengths
+        };
+        assert!(lengths(&[5000]).are_minified());
+        assert!(lengths(&[1001, 10]).are_minified());
+        // A single long line in a file of short ones doesn't make it minified.
+        assert!(!lengths(&[1001, 10, 10, 10, 10, 10]).are_minified());
+        assert!(!lengths(&[900; 10]).are_minified());
+        assert!(!lengths(&[]).are_minified());
+    }
+}
@@ -0,0 +1,107 @@
+//! Temporary directories and git repositories shared by the tests of several modules.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use git2::{Repository, Signature, Time};
+use ignore::overrides::{Override, OverrideBuilder};
+
+use crate::{count_rev, CountResult, Options};
+
+/// Globs relative to `root` as given on the command line, with excluded ones negated.
+pub(crate) fn globs(root: &Path, include: &[&str], exclude: &[&str]) -> Override {
+    let mut globs = OverrideBuilder::new(root);
+    for glob in include {
+        globs.add(glob).unwrap();
+    }
+    for glob in exclude {
+        globs.add(&format!("!{glob}")).unwrap();
+    }
+    globs.build().unwrap()
+}
+
+/// A directory of its own under the system's temporary directory, removed when dropped.
+pub(crate) struct TempDir {
+    pub path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates an empty directory, named after `name` and the process so tests running at
+    /// the same time don't share it.
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("lc-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    /// Writes `files`, given as paths relative to the directory and contents, creating
+    /// their parent directories.
+    pub fn write(&self, files: &[(&str, &str)]) {
+        for (path, src) in files {
+            let full = self.path.join(path);
+            fs::create_dir_all(full.parent().unwrap()).unwrap();
+            fs::write(full, src).unwrap();
+        }
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A repository in a directory of its own, removed when dropped.
+pub(crate) struct TestRepo {
+    pub dir: TempDir,
+    pub repo: Repository,
+}
+
+impl TestRepo {
+    pub fn new(name: &str) -> Self {
+        let dir = TempDir::new(name);
+        let repo = Repository::init(&dir.path).unwrap();
+        Self { dir, repo }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.dir.path
+    }
+
+    /// Commits `files`, given as paths and contents, at `time` in seconds since the epoch.
+    pub fn commit(&self, files: &[(&str, &str)], time: i64) {
+        self.commit_as(("A", "a@example.com"), files, time);
+    }
+
+    /// Like [`TestRepo::commit`], by the author with the given name and email.
+    pub fn commit_as(&self, (name, email): (&str, &str), files: &[(&str, &str)], time: i64) {
+        self.dir.write(files);
+        let mut index = self.repo.index().unwrap();
+        for (path, _) in files {
+            index.add_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = self.repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let author = Signature::new(name, email, &Time::new(time, 0)).unwrap();
+        let parent = self
+            .repo
+            .head()
+            .ok()
+            .map(|head| head.peel_to_commit().unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+        self.repo
+            .commit(Some("HEAD"), &author, &author, "commit", &tree, &parents)
+            .unwrap();
+    }
+
+    /// Counts `rev` like [`count_rev`], with paths relative to the repository.
+    pub fn count(&self, rev: &str, options: &Options) -> CountResult {
+        let mut res = count_rev(&[self.path()], rev, options).unwrap();
+        res.strip_prefix(self.path());
+        res
+    }
+}